@@ -0,0 +1,349 @@
+// An opening book: for positions near the start of the game, the moves worth playing with a
+// weight each, looked up by the position's hash. Books are built from PDN games or from searches
+// of the opening (see bin/book.rs) and let the engine play its first moves without searching.
+//
+// A move is stored as its start and end square. Two captures with the same start and end but a
+// different path are told apart by neither, the first legal one is played.
+
+use super::engine::SearchLimits;
+use super::heuristic::Heuristic;
+use super::{analyze, move_key, MoveKey};
+use crate::board::pdn::{PdnGame, PdnResult};
+use crate::board::{Board, Player};
+use anyhow::{ensure, Context, Result};
+use rand::Rng;
+use std::collections::HashMap;
+use std::fs::{read, write};
+
+const MAGIC: &[u8; 4] = b"CKOB";
+const VERSION: u8 = 1;
+// moves found by a search are kept while they are at most this much worse than the best one
+const SEARCH_MARGIN: i32 = 2500;
+
+// how a move is picked when the book has several for a position
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BookSelection {
+    // always the one with the largest weight
+    #[default]
+    Best,
+    // at random, as likely as its share of the weights
+    Weighted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BookMove {
+    mv: MoveKey,
+    weight: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OpeningBook {
+    entries: HashMap<u64, Vec<BookMove>>,
+    selection: BookSelection,
+}
+
+impl OpeningBook {
+    pub fn set_selection(&mut self, selection: BookSelection) {
+        self.selection = selection;
+    }
+
+    pub fn get_selection(&self) -> BookSelection {
+        self.selection
+    }
+
+    // number of positions in the book
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // adds `weight` to move `mv` of the board, the move is added if it isn't in the book yet
+    pub fn add(&mut self, b: &Board, mv: usize, weight: u32) {
+        let mv = move_key(&b.get_player_info().get_moves()[mv]);
+        let moves = self.entries.entry(b.get_hash()).or_default();
+        match moves.iter_mut().find(|m| m.mv == mv) {
+            Some(m) => m.weight = m.weight.saturating_add(weight),
+            None => moves.push(BookMove { mv, weight }),
+        }
+    }
+
+    // the book moves of the board as indices of its legal moves with their weights, largest first
+    pub fn get_moves(&self, b: &Board) -> Vec<(usize, u32)> {
+        let legal = b.get_player_info().get_moves();
+        let mut moves: Vec<(usize, u32)> = self
+            .entries
+            .get(&b.get_hash())
+            .into_iter()
+            .flatten()
+            .filter(|m| m.weight > 0)
+            .filter_map(|m| {
+                let mv = legal.iter().position(|l| move_key(l) == m.mv)?;
+                Some((mv, m.weight))
+            })
+            .collect();
+        moves.sort_by_key(|&(mv, weight)| (std::cmp::Reverse(weight), mv));
+        moves
+    }
+
+    // the move to play from the book, None once the game has left it
+    pub fn choose(&self, b: &Board, rng: &mut impl Rng) -> Option<usize> {
+        let moves = self.get_moves(b);
+        match self.selection {
+            BookSelection::Best => moves.first().map(|&(mv, _)| mv),
+            BookSelection::Weighted => {
+                let total: u64 = moves.iter().map(|&(_, weight)| weight as u64).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut pick = rng.gen_range(0..total);
+                for (mv, weight) in moves {
+                    if pick < weight as u64 {
+                        return Some(mv);
+                    }
+                    pick -= weight as u64;
+                }
+                unreachable!("the pick is below the total weight")
+            }
+        }
+    }
+
+    // The first `plies` moves of every game. A move counts twice when its side went on to win
+    // and not at all when it lost, so the book leans towards the moves that did well.
+    pub fn from_games(games: &[PdnGame], plies: usize) -> Result<Self> {
+        let mut book = Self::default();
+        for (i, game) in games.iter().enumerate() {
+            let positions = game
+                .replay()
+                .with_context(|| format!("Could not replay game {}", i + 1))?;
+            for (b, mv) in positions.iter().take(plies) {
+                let weight = match (game.get_result(), b.get_current_player()) {
+                    (PdnResult::BlackWin, Player::Black) | (PdnResult::RedWin, Player::Red) => 2,
+                    (PdnResult::BlackWin, Player::Red) | (PdnResult::RedWin, Player::Black) => 0,
+                    _ => 1,
+                };
+                if weight > 0 {
+                    book.add(b, *mv, weight);
+                }
+            }
+        }
+        Ok(book)
+    }
+
+    // Searches the start position with multi-PV and follows every move that is close enough to
+    // the best one, `plies` moves deep. Of the `lines` moves of a position the best one gets a
+    // weight of `lines`, the next one `lines - 1` and so on.
+    pub fn from_search(
+        start: &Board,
+        plies: usize,
+        lines: usize,
+        limits: &SearchLimits,
+        h_s: &Heuristic,
+    ) -> Self {
+        let mut book = Self::default();
+        let mut positions = vec![start.clone()];
+        for _ in 0..plies {
+            let mut next = Vec::new();
+            for b in positions {
+                if b.is_game_over().is_some() || book.entries.contains_key(&b.get_hash()) {
+                    continue;
+                }
+                let results = analyze(b.clone(), limits.clone(), Some(h_s.clone()), lines, None);
+                let best = match results.first().and_then(|r| r.score) {
+                    Some(best) => best,
+                    None => continue,
+                };
+                for (rank, result) in results.iter().enumerate() {
+                    if result
+                        .score
+                        .is_some_and(|score| score < best - SEARCH_MARGIN)
+                    {
+                        break;
+                    }
+                    book.add(&b, result.best_move, (lines - rank) as u32);
+                    let mut after = b.clone();
+                    after.do_move(result.best_move);
+                    next.push(after);
+                }
+            }
+            positions = next;
+        }
+        book
+    }
+
+    // MAGIC, VERSION, number of positions as a little endian u32, then every position as its
+    // hash (u64), number of moves (u8) and each move as start and end square (u8 each) and its
+    // weight (u32)
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        let mut hashes: Vec<&u64> = self.entries.keys().collect();
+        hashes.sort();
+        for hash in hashes {
+            let moves = &self.entries[hash];
+            bytes.extend_from_slice(&hash.to_le_bytes());
+            // checkers positions never have close to 256 moves
+            bytes.push(moves.len() as u8);
+            for m in moves {
+                bytes.push(m.mv.0 as u8);
+                bytes.push(m.mv.1 as u8);
+                bytes.extend_from_slice(&m.weight.to_le_bytes());
+            }
+        }
+        write(path, bytes).with_context(|| format!("Could not write {}", path))
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let bytes = read(path).with_context(|| format!("Could not read {}", path))?;
+        Self::from_bytes(&bytes).with_context(|| format!("Invalid opening book file {}", path))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut rest = bytes;
+        let mut take = |n: usize| -> Result<&[u8]> {
+            ensure!(rest.len() >= n, "the file ends early");
+            let (taken, left) = rest.split_at(n);
+            rest = left;
+            Ok(taken)
+        };
+        ensure!(take(4)? == MAGIC, "not an opening book file");
+        ensure!(take(1)?[0] == VERSION, "unknown version");
+        let count = u32::from_le_bytes(take(4)?.try_into()?);
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            let hash = u64::from_le_bytes(take(8)?.try_into()?);
+            let len = take(1)?[0];
+            let mut moves = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                let squares = take(2)?;
+                ensure!(
+                    squares.iter().all(|&sq| sq < 32),
+                    "a move has a square off the board"
+                );
+                moves.push(BookMove {
+                    mv: (squares[0] as usize, squares[1] as usize),
+                    weight: u32::from_le_bytes(take(4)?.try_into()?),
+                });
+            }
+            entries.insert(hash, moves);
+        }
+        ensure!(take(1).is_err(), "unexpected data after the last position");
+        Ok(Self {
+            entries,
+            selection: BookSelection::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ai::engine::SearchOptions;
+    use crate::ai::{new_rng, predict_move_threaded};
+    use crate::board::pdn::parse_pdn;
+
+    const GAMES: &str = r#"[Result "1-0"]
+1. 11-15 23-19 2. 8-11 22-17 1-0
+
+[Result "0-1"]
+1. 11-15 24-20 2. 15-18 0-1
+
+[Result "1/2-1/2"]
+1. 9-14 22-18 1/2-1/2
+"#;
+
+    fn notation(b: &Board, mv: usize) -> String {
+        b.get_player_info().get_moves()[mv].notation()
+    }
+
+    #[test]
+    fn test_from_games() {
+        let book = OpeningBook::from_games(&parse_pdn(GAMES).unwrap(), 2).unwrap();
+        let b = Board::default();
+        let moves: Vec<(String, u32)> = book
+            .get_moves(&b)
+            .into_iter()
+            .map(|(mv, weight)| (notation(&b, mv), weight))
+            .collect();
+        // won once and lost once, against a draw
+        assert_eq!(
+            moves,
+            vec![("11-15".to_string(), 2), ("9-14".to_string(), 1)]
+        );
+        let mv = book.choose(&b, &mut new_rng(None)).unwrap();
+        assert_eq!(notation(&b, mv), "11-15");
+
+        let mut b = b;
+        b.do_move(b.parse_move("11-15").unwrap());
+        // 23-19 was played by the side that lost
+        let moves = book.get_moves(&b);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(notation(&b, moves[0].0), "24-20");
+        // the third ply is past the two that were read
+        b.do_move(moves[0].0);
+        assert_eq!(book.choose(&b, &mut new_rng(None)), None);
+    }
+
+    #[test]
+    fn test_selection() {
+        let b = Board::default();
+        let mut book = OpeningBook::default();
+        book.add(&b, 0, 1);
+        book.add(&b, 3, 3);
+        let mut rng = new_rng(Some(1));
+        assert_eq!(book.choose(&b, &mut rng), Some(3));
+        let options = SearchOptions {
+            book: Some(&book),
+            ..Default::default()
+        };
+        let result = predict_move_threaded(b.clone(), SearchLimits::from_depth(1), None, &options);
+        assert_eq!(result.best_move, 3);
+
+        book.set_selection(BookSelection::Weighted);
+        let mut counts = [0; 2];
+        for _ in 0..400 {
+            match book.choose(&b, &mut rng) {
+                Some(0) => counts[0] += 1,
+                Some(3) => counts[1] += 1,
+                other => panic!("{:?} isn't a book move", other),
+            }
+        }
+        assert!(counts[0] > 0 && counts[1] > counts[0]);
+    }
+
+    #[test]
+    fn test_from_search() {
+        let b = Board::default();
+        let limits = SearchLimits::from_depth(2);
+        let book = OpeningBook::from_search(&b, 2, 2, &limits, &Heuristic::default_new());
+        let moves = book.get_moves(&b);
+        assert!(!moves.is_empty() && moves.len() <= 2);
+        assert_eq!(moves[0].1, 2);
+        // every move that was followed has the replies of the second ply
+        for (mv, _) in moves {
+            let mut after = b.clone();
+            after.do_move(mv);
+            assert!(!book.get_moves(&after).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_file() {
+        let mut book = OpeningBook::from_games(&parse_pdn(GAMES).unwrap(), 4).unwrap();
+        let path = std::env::temp_dir().join("checkers_test.book");
+        let path = path.to_str().unwrap();
+        book.save(path).unwrap();
+        let loaded = OpeningBook::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.entries, book.entries);
+        book.entries.clear();
+        book.save(path).unwrap();
+        assert!(OpeningBook::load(path).unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+        assert!(OpeningBook::from_bytes(b"CKOB\x01\x01\x00\x00\x00").is_err());
+    }
+}
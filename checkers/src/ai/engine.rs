@@ -0,0 +1,417 @@
+// Anything that can pick a move for the player to move. The game loop and the trainer only talk
+// to engines through this trait so any mix of players can be put against each other.
+
+use super::book::OpeningBook;
+use super::tablebase::Tablebase;
+use super::{analyze, heuristic::Heuristic, new_rng, predict_move_threaded, MAX, WIN_BOUND};
+use crate::board::Board;
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::io::stdin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::Duration;
+
+// When a search has to stop, whichever limit is hit first. With no limits at all it only stops once
+// the stop flag is set or the result of the game is proven.
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    pub time: Option<Duration>,
+    // positions the search may visit
+    pub nodes: Option<u64>,
+    pub depth: Option<u32>,
+    // can be set from another thread to stop the search, it then returns the best move so far
+    pub stop: Option<Arc<AtomicBool>>,
+    // gets the result of every depth as soon as it is done, for showing the search as it goes
+    pub info: Option<Sender<SearchResult>>,
+}
+
+impl SearchLimits {
+    pub fn from_secs(secs: u64) -> Self {
+        Self {
+            time: Some(Duration::from_secs(secs)),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_depth(depth: u32) -> Self {
+        Self {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_nodes(nodes: u64) -> Self {
+        Self {
+            nodes: Some(nodes),
+            ..Default::default()
+        }
+    }
+
+    // nothing would ever end the search, engines fall back to a budget of their own
+    pub fn is_unlimited(&self) -> bool {
+        self.time.is_none() && self.nodes.is_none() && self.depth.is_none() && self.stop.is_none()
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|s| s.load(Ordering::Relaxed))
+    }
+}
+
+// What the alpha beta search can use besides the position, its limits and the heuristic.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions<'a> {
+    // threads searching the position, 1 by default
+    pub threads: usize,
+    pub tablebase: Option<&'a Tablebase>,
+    pub book: Option<&'a OpeningBook>,
+    // seeds the evaluation noise and the pick of a weighted book, when None they are random
+    pub seed: Option<u64>,
+    // Noise in the evaluation keeps equal moves from always being played the same. Without it a
+    // search on one thread that isn't cut off by time always comes to the same result.
+    pub noise: bool,
+}
+
+impl Default for SearchOptions<'_> {
+    fn default() -> Self {
+        Self {
+            threads: 1,
+            tablebase: None,
+            book: None,
+            seed: None,
+            noise: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResult {
+    // index into the current player's moves
+    pub best_move: usize,
+    // from the point of view of the player to move, None for engines that don't score positions
+    pub score: Option<i32>,
+    // the expected line starting with best_move, each move an index into the moves of the
+    // position before it
+    pub pv: Vec<usize>,
+    // last depth that was searched completely
+    pub depth: u32,
+    pub nodes: u64,
+    pub time: Duration,
+    // the engine gives up instead of moving, best_move means nothing then
+    pub resign: bool,
+}
+
+impl SearchResult {
+    pub fn new(best_move: usize) -> Self {
+        Self {
+            best_move,
+            ..Default::default()
+        }
+    }
+
+    pub fn resign() -> Self {
+        Self {
+            resign: true,
+            ..Default::default()
+        }
+    }
+
+    // plies until the end of the game if the search found a forced win, positive when the player
+    // to move wins and negative when they lose
+    pub fn mate_in(&self) -> Option<i32> {
+        match self.score? {
+            score if score > WIN_BOUND => Some(MAX - score),
+            score if score < -WIN_BOUND => Some(-(MAX + score)),
+            _ => None,
+        }
+    }
+
+    pub fn nps(&self) -> u64 {
+        (self.nodes as f64 / self.time.as_secs_f64().max(1e-3)) as u64
+    }
+
+    // the principal variation written out, played from the position that was searched
+    pub fn pv_notation(&self, b: &Board) -> Vec<String> {
+        let mut b = b.clone();
+        let mut line = Vec::new();
+        for &mv in self.pv.iter() {
+            match b.get_player_info().get_moves().get(mv) {
+                Some(m) => line.push(m.notation()),
+                None => break,
+            }
+            b.do_move(mv);
+        }
+        line
+    }
+
+    // one line summary of the search, e.g. "depth 12 score 350 pv 11-15 23-19 nodes 1523044 nps 1496112 time 1.018s"
+    pub fn analysis(&self, b: &Board) -> String {
+        let mut s = format!("depth {}", self.depth);
+        match (self.mate_in(), self.score) {
+            (Some(plies), _) if plies > 0 => s += &format!(" score win in {}", plies),
+            (Some(plies), _) => s += &format!(" score loss in {}", -plies),
+            (None, Some(score)) => s += &format!(" score {}", score),
+            (None, None) => {}
+        }
+        let pv = self.pv_notation(b);
+        if !pv.is_empty() {
+            s += &format!(" pv {}", pv.join(" "));
+        }
+        s + &format!(
+            " nodes {} nps {} time {:.3}s",
+            self.nodes,
+            self.nps(),
+            self.time.as_secs_f64()
+        )
+    }
+}
+
+pub trait Engine {
+    fn name(&self) -> String;
+    // the board always has at least one legal move and the returned move has to be one of them
+    fn choose_move(&mut self, b: &Board, limits: SearchLimits) -> SearchResult;
+    // makes the engine's random choices the same every time, engines without any ignore it
+    fn set_seed(&mut self, _seed: u64) {}
+}
+
+// the iterative deepening alpha beta search of predict_move
+#[derive(Debug, Clone)]
+pub struct AlphaBeta {
+    h_s: Option<Heuristic>,
+    threads: usize,
+    // endgame tables shared by every copy of the engine
+    tablebase: Option<Arc<Tablebase>>,
+    book: Option<Arc<OpeningBook>>,
+    // every search gets a seed from this
+    rng: StdRng,
+    noise: bool,
+}
+
+impl AlphaBeta {
+    pub fn new(h_s: Heuristic) -> Self {
+        Self {
+            h_s: Some(h_s),
+            threads: 1,
+            tablebase: None,
+            book: None,
+            rng: new_rng(None),
+            noise: true,
+        }
+    }
+
+    // number of threads searching each move, 1 by default
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn get_threads(&self) -> usize {
+        self.threads
+    }
+
+    // positions the tables hold are looked up instead of searched
+    pub fn set_tablebase(&mut self, tablebase: Arc<Tablebase>) {
+        self.tablebase = Some(tablebase);
+    }
+
+    // see SearchOptions::noise
+    pub fn set_noise(&mut self, noise: bool) {
+        self.noise = noise;
+    }
+
+    // positions in the book are played from it, with the book's own selection
+    pub fn set_book(&mut self, book: Arc<OpeningBook>) {
+        self.book = Some(book);
+    }
+
+    // the best `lines` moves with their own scores and lines, best first. Always searched on one
+    // thread.
+    pub fn analyze(&self, b: &Board, limits: SearchLimits, lines: usize) -> Vec<SearchResult> {
+        analyze(
+            b.clone(),
+            limits,
+            self.h_s.clone(),
+            lines,
+            self.tablebase.as_deref(),
+        )
+    }
+}
+
+impl Default for AlphaBeta {
+    fn default() -> Self {
+        Self {
+            h_s: None,
+            threads: 1,
+            tablebase: None,
+            book: None,
+            rng: new_rng(None),
+            noise: true,
+        }
+    }
+}
+
+impl Engine for AlphaBeta {
+    fn name(&self) -> String {
+        String::from("Alpha/Beta")
+    }
+
+    fn choose_move(&mut self, b: &Board, limits: SearchLimits) -> SearchResult {
+        let options = SearchOptions {
+            threads: self.threads,
+            tablebase: self.tablebase.as_deref(),
+            book: self.book.as_deref(),
+            seed: Some(self.rng.gen()),
+            noise: self.noise,
+        };
+        predict_move_threaded(b.clone(), limits, self.h_s.clone(), &options)
+    }
+
+    // the same seed gives the same searches, as long as they run on one thread and aren't cut off
+    // by time
+    fn set_seed(&mut self, seed: u64) {
+        self.rng = new_rng(Some(seed));
+    }
+}
+
+// picks uniformly between the legal moves
+#[derive(Debug, Clone)]
+pub struct RandomMover {
+    rng: StdRng,
+}
+
+impl Default for RandomMover {
+    fn default() -> Self {
+        Self { rng: new_rng(None) }
+    }
+}
+
+impl Engine for RandomMover {
+    fn name(&self) -> String {
+        String::from("Random")
+    }
+
+    fn choose_move(&mut self, b: &Board, _limits: SearchLimits) -> SearchResult {
+        let moves = b.get_player_info().get_moves().len();
+        SearchResult::new(self.rng.gen_range(0..moves))
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.rng = new_rng(Some(seed));
+    }
+}
+
+// asks on stdin, a move can be picked by its number in the list or written out like 11-15.
+// "analyze" or "analyze <lines>" shows what the alpha beta search thinks of the position first,
+// "explain" or "explain json" how the heuristic scores it.
+#[derive(Debug, Clone, Default)]
+pub struct Human {
+    // the weights analyze and explain use
+    h_s: Heuristic,
+}
+
+const DEFAULT_ANALYSIS_LINES: usize = 3;
+
+impl Human {
+    pub fn new(h_s: Heuristic) -> Self {
+        Self { h_s }
+    }
+
+    fn print_analysis(&self, b: &Board, limits: &SearchLimits, args: &str) {
+        let lines = match args.trim() {
+            "" => DEFAULT_ANALYSIS_LINES,
+            n => match n.parse::<usize>() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    println!("Usage: analyze [number of lines]");
+                    return;
+                }
+            },
+        };
+        let results = AlphaBeta::new(self.h_s.clone()).analyze(b, limits.clone(), lines);
+        for (i, result) in results.iter().enumerate() {
+            println!("{}. {}", i + 1, result.analysis(b));
+        }
+    }
+
+    fn print_explanation(&self, b: &Board, args: &str) {
+        let breakdown = self.h_s.explain(b);
+        match args.trim() {
+            "" => println!("{}", breakdown),
+            "json" => println!(
+                "{}",
+                serde_json::to_string_pretty(&breakdown).expect("Err: breakdown to json")
+            ),
+            _ => println!("Usage: explain [json]"),
+        }
+    }
+}
+
+impl Engine for Human {
+    fn name(&self) -> String {
+        String::from("Human")
+    }
+
+    fn choose_move(&mut self, b: &Board, limits: SearchLimits) -> SearchResult {
+        let moves = b.get_player_info().get_moves().len();
+        loop {
+            println!("Please Pick a Move by its number or in notation (e.g. 11-15, 22x15x8), or type analyze [lines] or explain [json]");
+            let mut s = String::new();
+            match stdin().read_line(&mut s) {
+                // stdin is closed, there is nobody left to ask
+                Ok(0) => {
+                    println!("Input closed while waiting for a move, resigning");
+                    return SearchResult::resign();
+                }
+                Err(_) => {
+                    println!("Invalid Input");
+                    continue;
+                }
+                Ok(_) => {}
+            }
+            let s = s.trim();
+            if let Some(args) = s.strip_prefix("analyze") {
+                self.print_analysis(b, &limits, args);
+                continue;
+            }
+            if let Some(args) = s.strip_prefix("explain") {
+                self.print_explanation(b, args);
+                continue;
+            }
+            match s.parse::<usize>() {
+                Ok(x) if x < moves => return SearchResult::new(x),
+                Ok(x) => println!("Please Enter a Number in the range, you tried {}", x),
+                Err(_) => match b.parse_move(s) {
+                    Ok(mv) => return SearchResult::new(mv),
+                    Err(e) => println!("{}", e),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_engines_pick_legal_moves() {
+        let mut b = Board::default();
+        let mut random = RandomMover::default();
+        let mut alpha_beta = AlphaBeta::default();
+        while b.is_game_over().is_none() {
+            let mv = random.choose_move(&b, SearchLimits::from_secs(1)).best_move;
+            assert!(mv < b.get_player_info().get_moves().len());
+            b.do_move(mv);
+        }
+
+        // with a single legal move the search doesn't have to run
+        let b = Board::from_fen("B:W14:B10").unwrap();
+        assert_eq!(b.get_player_info().get_moves().len(), 1);
+        let mv = alpha_beta
+            .choose_move(&b, SearchLimits::from_secs(1))
+            .best_move;
+        assert_eq!(mv, 0);
+    }
+}
@@ -1,9 +1,99 @@
 use crate::board::{Board, BoardPiece, Cord, Player};
+use anyhow::{Context, Result};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs::{read_to_string, write};
 
 type PieceType = (BoardPiece, Cord);
+// number of weights in a Heuristic
+pub const WEIGHT_COUNT: usize = 11;
+// the names of the weights in a weight file, in the order of get_weights
+pub const WEIGHT_NAMES: [&str; WEIGHT_COUNT] = [
+    "n_piece_val",
+    "k_piece_val",
+    "d_hr_mul",
+    "true_center",
+    "off_center",
+    "goalies_center",
+    "goalies_side",
+    "per_move_val",
+    "per_jump_move_val",
+    "aggresion_multiplier",
+    "distance_penalty",
+];
 
-#[derive(Clone, Debug)]
+// what one side's pieces are worth to h, each field is already multiplied by its weight
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SideTerms {
+    pub material: i32,
+    // men by how far they have come from their home row
+    pub home_row_distance: i32,
+    pub center: i32,
+    // men still guarding the home row
+    pub goalies: i32,
+    // a penalty in the end game for the king furthest from the opponent's pieces
+    pub king_distance: i32,
+}
+
+impl SideTerms {
+    pub fn total(&self) -> i32 {
+        self.material + self.home_row_distance + self.center + self.goalies + self.king_distance
+    }
+}
+
+// h(state, true) is mine.total() - theirs.total() + mobility + aggression
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EvalBreakdown {
+    // the player to move, whose point of view the scores are from
+    pub player: Player,
+    pub mine: SideTerms,
+    pub theirs: SideTerms,
+    // only the player to move's moves are counted
+    pub mobility: i32,
+    // the bonus for trading down while ahead, negative when behind
+    pub aggression: i32,
+    pub total: i32,
+}
+
+impl Display for EvalBreakdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows = [
+            ("material", self.mine.material, self.theirs.material),
+            (
+                "home row distance",
+                self.mine.home_row_distance,
+                self.theirs.home_row_distance,
+            ),
+            ("center", self.mine.center, self.theirs.center),
+            ("goalies", self.mine.goalies, self.theirs.goalies),
+            (
+                "king distance",
+                self.mine.king_distance,
+                self.theirs.king_distance,
+            ),
+            ("sum", self.mine.total(), self.theirs.total()),
+        ];
+        writeln!(
+            f,
+            "{:<18}{:>10}{:>10}",
+            "",
+            format!("{:?}", self.player),
+            format!("{:?}", self.player.get_other())
+        )?;
+        for (name, mine, theirs) in rows {
+            writeln!(f, "{:<18}{:>10}{:>10}", name, mine, theirs)?;
+        }
+        writeln!(f, "{:<18}{:>10}", "mobility", self.mobility)?;
+        writeln!(f, "{:<18}{:>10}", "aggression", self.aggression)?;
+        write!(f, "{:<18}{:>10} for {:?}", "total", self.total, self.player)
+    }
+}
+
+// Weight files are the JSON form of this struct, e.g. {"n_piece_val": 10000, "k_piece_val": 15000},
+// any weight left out keeps its default value.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Heuristic {
     // Normal Piece Value
     n_piece_val: i32,
@@ -30,7 +120,7 @@ pub struct Heuristic {
 }
 
 impl Heuristic {
-    #[allow(dead_code)]
+    #[allow(dead_code, clippy::too_many_arguments)]
     pub fn new(
         n_piece_val: i32,
         k_piece_val: i32,
@@ -75,135 +165,126 @@ impl Heuristic {
         }
     }
 
-    pub fn mutate(&self) -> Self {
-        let mut rng = rand::thread_rng();
-        let rng_n_piece_val = std::cmp::max(1, self.n_piece_val / 10);
-        let rng_k_piece_val = std::cmp::max(1, self.k_piece_val / 10);
-        let rng_d_hr_mul = std::cmp::max(1, self.d_hr_mul / 10);
-        let rng_true_center = std::cmp::max(1, self.true_center / 10);
-        let rng_off_center = std::cmp::max(1, self.off_center / 10);
-        let rng_goalies_center = std::cmp::max(1, self.goalies_center / 10);
-        let rng_goalies_side = std::cmp::max(1, self.goalies_side / 10);
-        let rng_per_move_val = std::cmp::max(1, self.per_move_val / 10);
-        let rng_per_jump_move_val = std::cmp::max(1, self.per_jump_move_val / 10);
-        let rng_aggresion_multiplier = std::cmp::max(1, self.aggresion_multiplier / 10);
-        let rng_distance_penalty = std::cmp::max(1, self.distance_penalty / 10);
-
-        Self::new(
-            std::cmp::min(
-                0,
-                self.n_piece_val + rng.gen_range(-rng_n_piece_val..rng_n_piece_val),
-            ),
-            std::cmp::min(
-                0,
-                self.k_piece_val + rng.gen_range(-rng_k_piece_val..rng_k_piece_val),
-            ),
-            std::cmp::min(
-                0,
-                self.d_hr_mul + rng.gen_range(-rng_d_hr_mul..rng_d_hr_mul),
-            ),
-            std::cmp::min(
-                0,
-                self.true_center + rng.gen_range(-rng_true_center..rng_true_center),
-            ),
-            std::cmp::min(
-                0,
-                self.off_center + rng.gen_range(-rng_off_center..rng_off_center),
-            ),
-            std::cmp::min(
-                0,
-                self.goalies_center + rng.gen_range(-rng_goalies_center..rng_goalies_center),
-            ),
-            std::cmp::min(
-                0,
-                self.goalies_side + rng.gen_range(-rng_goalies_side..rng_goalies_side),
-            ),
-            std::cmp::min(
-                0,
-                self.per_move_val + rng.gen_range(-rng_per_move_val..rng_per_move_val),
-            ),
-            std::cmp::min(
-                0,
-                self.per_jump_move_val
-                    + rng.gen_range(-rng_per_jump_move_val..rng_per_jump_move_val),
-            ),
-            std::cmp::min(
-                0,
-                self.aggresion_multiplier
-                    + rng.gen_range(-rng_aggresion_multiplier..rng_aggresion_multiplier),
-            ),
-            std::cmp::min(
-                0,
-                self.distance_penalty + rng.gen_range(-rng_distance_penalty..rng_distance_penalty),
-            ),
-        )
+    pub fn load(path: &str) -> Result<Self> {
+        let file = read_to_string(path).with_context(|| format!("Could not read {}", path))?;
+        serde_json::from_str(&file).with_context(|| format!("Invalid weight file {}", path))
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        write(path, json + "\n").with_context(|| format!("Could not write {}", path))
+    }
+
+    // the weights in the order of the fields, for changing them all the same way
+    pub fn get_weights(&self) -> [i32; WEIGHT_COUNT] {
+        [
+            self.n_piece_val,
+            self.k_piece_val,
+            self.d_hr_mul,
+            self.true_center,
+            self.off_center,
+            self.goalies_center,
+            self.goalies_side,
+            self.per_move_val,
+            self.per_jump_move_val,
+            self.aggresion_multiplier,
+            self.distance_penalty,
+        ]
+    }
+
+    pub fn from_weights(weights: [i32; WEIGHT_COUNT]) -> Self {
+        Self {
+            n_piece_val: weights[0],
+            k_piece_val: weights[1],
+            d_hr_mul: weights[2],
+            true_center: weights[3],
+            off_center: weights[4],
+            goalies_center: weights[5],
+            goalies_side: weights[6],
+            per_move_val: weights[7],
+            per_jump_move_val: weights[8],
+            aggresion_multiplier: weights[9],
+            distance_penalty: weights[10],
+        }
+    }
+
+    // Moves every weight by up to a tenth of its value (at least 1) either way. All the weights
+    // are bonuses or penalties whose sign is already in how they are used, so none goes below 0.
+    pub fn mutate(&self, rng: &mut impl Rng) -> Self {
+        Self::from_weights(self.get_weights().map(|w| {
+            let spread = std::cmp::max(1, w / 10);
+            std::cmp::max(0, w + rng.gen_range(-spread..=spread))
+        }))
+    }
+
+    // uniform crossover, every weight comes from one of the two parents
+    pub fn crossover(&self, other: &Self, rng: &mut impl Rng) -> Self {
+        let mine = self.get_weights();
+        let theirs = other.get_weights();
+        Self::from_weights(std::array::from_fn(|i| match rng.gen_bool(0.5) {
+            true => mine[i],
+            false => theirs[i],
+        }))
     }
 
     pub fn h(&self, state: &Board, is_max: bool) -> i32 {
-        let (my_pieces, other_pieces) = state.get_pieces();
-        let mut score = 0;
+        let score = self.explain(state).total;
+        if !is_max {
+            return -score;
+        }
+        score
+    }
 
+    // h split into its terms, from the point of view of the player to move
+    pub fn explain(&self, state: &Board) -> EvalBreakdown {
+        let (my_pieces, other_pieces) = state.get_pieces();
         let is_end_game = my_pieces.len() + other_pieces.len() < 6;
 
-        let per_piece = |pt: &PieceType, plyr: Player, my_ps_flag: bool| {
-            let (bp, bc) = pt;
-            let mut current_score = self.piece_type_value(bp);
+        // both sides are scored against the home rows of the player to move
+        let side_terms = |pieces: &[PieceType], op_pieces: &[PieceType], plyr: Player| {
+            let mut terms = SideTerms::default();
             let mut max_distance = 0;
-            if !bp.is_king() {
-                current_score += self.depth_distance(
+            for (bp, bc) in pieces.iter() {
+                terms.material += self.piece_type_value(bp);
+                if !bp.is_king() {
+                    terms.home_row_distance += self.depth_distance(
+                        bc,
+                        match plyr {
+                            Player::Red => 0,
+                            Player::Black => 7,
+                        },
+                    );
+                } else if is_end_game {
+                    max_distance = std::cmp::max(max_distance, self.square_distance(bc, op_pieces));
+                }
+                terms.center += self.in_center(bc);
+                terms.goalies += self.in_goal(
                     bc,
                     match plyr {
-                        Player::Red => 0,
-                        Player::Black => 7,
+                        Player::Red => 7,
+                        Player::Black => 0,
                     },
                 );
-            } else if is_end_game {
-                let pieces = match my_ps_flag {
-                    true => &other_pieces,
-                    false => &my_pieces,
-                };
-                max_distance = self.square_distance(bc, pieces);
             }
-            current_score += self.in_center(bc);
-            current_score += self.in_goal(
-                bc,
-                match plyr {
-                    Player::Red => 7,
-                    Player::Black => 0,
-                },
-            );
-
-            return (current_score, max_distance);
-        };
-
-        let fold_func = |plyr: Player, my_ps_flag: bool| {
-            return move |(prev_score, prev_max): (i32, i32), pt: &PieceType| {
-                let (score, max_distance) = per_piece(pt, plyr, my_ps_flag);
-                return (prev_score + score, std::cmp::max(max_distance, prev_max));
-            };
+            terms.king_distance = -max_distance * self.distance_penalty;
+            terms
         };
 
-        let (score_adder, max_my_d) = my_pieces
-            .iter()
-            .fold((0, 0), fold_func(state.get_current_player(), true));
-        score += score_adder;
-        score -= max_my_d * self.distance_penalty;
-
-        let (score_subber, max_ot_d) = other_pieces
-            .iter()
-            .fold((0, 0), fold_func(state.get_current_player(), false));
-        score -= score_subber;
-        score += max_ot_d * self.distance_penalty;
-
-        score += self.mobility(state);
-
-        score += self.aggresion_value(my_pieces.len() as f32, other_pieces.len() as f32);
-
-        if !is_max {
-            score = -score;
+        let plyr = state.get_current_player();
+        let mine = side_terms(&my_pieces, &other_pieces, plyr);
+        let theirs = side_terms(&other_pieces, &my_pieces, plyr);
+        let mobility = self.mobility(state);
+        let aggression = self.aggresion_value(my_pieces.len() as f32, other_pieces.len() as f32);
+        EvalBreakdown {
+            player: plyr,
+            mine,
+            theirs,
+            mobility,
+            aggression,
+            total: mine.total() - theirs.total() + mobility + aggression,
         }
-        return score;
     }
+
     fn mobility(&self, state: &Board) -> i32 {
         match state.get_player_info().get_can_jump() {
             true => state.get_player_info().get_moves().len() as i32 * self.per_jump_move_val,
@@ -221,7 +302,7 @@ impl Heuristic {
             return self.goalies_side;
         }
 
-        return 0;
+        0
     }
 
     fn in_center(&self, cords: &Cord) -> i32 {
@@ -232,11 +313,11 @@ impl Heuristic {
             }
             return self.off_center;
         }
-        return 0;
+        0
     }
 
     fn depth_distance(&self, cords: &Cord, goal: i32) -> i32 {
-        return (7 - (cords.1 as i32 - goal).abs()) * self.d_hr_mul;
+        (7 - (cords.1 as i32 - goal).abs()) * self.d_hr_mul
     }
 
     fn piece_type_value(&self, piece: &BoardPiece) -> i32 {
@@ -246,7 +327,7 @@ impl Heuristic {
         }
     }
 
-    fn square_distance(&self, &(row, col): &Cord, op_pieces: &Vec<(BoardPiece, Cord)>) -> i32 {
+    fn square_distance(&self, &(row, col): &Cord, op_pieces: &[PieceType]) -> i32 {
         op_pieces.iter().fold(0, |prev, &(_, (e_row, e_col))| {
             std::cmp::max(
                 prev,
@@ -268,6 +349,95 @@ impl Heuristic {
             big = op_piece_count;
             little = cp_piece_count;
         }
-        return ((big / little) * self.aggresion_multiplier as f32 * sign).ceil() as i32;
+        ((big / little) * self.aggresion_multiplier as f32 * sign).ceil() as i32
+    }
+}
+
+impl Default for Heuristic {
+    fn default() -> Self {
+        Self::default_new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_explain_adds_up() {
+        let h_s = Heuristic::default_new();
+        for fen in [
+            "B:W21,22,23,24,25,26,27,28,29,30,31,32:B1,2,3,4,5,6,7,8,9,10,11,12",
+            "W:WK14,22,27:B6,K11,12",
+            "B:W28:BK18,K19",
+        ] {
+            let b = Board::from_fen(fen).unwrap();
+            let e = h_s.explain(&b);
+            assert_eq!(e.player, b.get_current_player());
+            assert_eq!(
+                e.total,
+                e.mine.total() - e.theirs.total() + e.mobility + e.aggression
+            );
+            assert_eq!(h_s.h(&b, true), e.total);
+            assert_eq!(h_s.h(&b, false), -e.total);
+        }
+
+        // the start position is the same for both sides apart from who is to move
+        let e = h_s.explain(&Board::default());
+        assert_eq!(e.mine.material, e.theirs.material);
+        assert_eq!(e.aggression, 0);
+        assert_eq!(e.mobility, 7 * 400);
+        let json = serde_json::to_value(&e).unwrap();
+        assert_eq!(json["mine"]["material"], 120000);
+        assert_eq!(json["total"], e.total);
+    }
+    #[test]
+    fn test_weight_file() {
+        let mut h_s = Heuristic::default_new();
+        h_s.per_move_val = 123;
+        let path = std::env::temp_dir().join("checkers_test_weights.json");
+        let path = path.to_str().unwrap();
+        h_s.save(path).unwrap();
+        assert_eq!(Heuristic::load(path).unwrap(), h_s);
+        std::fs::remove_file(path).unwrap();
+
+        // missing weights keep their defaults, misspelled ones are an error
+        let partial: Heuristic = serde_json::from_str(r#"{"k_piece_val": 20000}"#).unwrap();
+        assert_eq!(partial.k_piece_val, 20000);
+        assert_eq!(partial.n_piece_val, Heuristic::default_new().n_piece_val);
+        assert!(serde_json::from_str::<Heuristic>(r#"{"k_piece": 1}"#).is_err());
+        assert!(Heuristic::load("no/such/weights.json").is_err());
+    }
+
+    #[test]
+    fn test_mutate_and_crossover() {
+        let h_s = Heuristic::default_new();
+        assert_eq!(Heuristic::from_weights(h_s.get_weights()), h_s);
+        let mutant = |seed| h_s.mutate(&mut crate::ai::new_rng(Some(seed)));
+        assert_eq!(mutant(3), mutant(3));
+
+        let json = serde_json::to_value(&h_s).unwrap();
+        for (name, w) in WEIGHT_NAMES.iter().zip(h_s.get_weights()) {
+            assert_eq!(json[name], w);
+        }
+
+        let mut rng = crate::ai::new_rng(Some(4));
+        let zero = Heuristic::from_weights([0; WEIGHT_COUNT]);
+        for _ in 0..20 {
+            // weights stay within a tenth of where they were and never go negative
+            for (w, m) in h_s
+                .get_weights()
+                .iter()
+                .zip(h_s.mutate(&mut rng).get_weights())
+            {
+                assert!((m - w).abs() <= w / 10, "{} became {}", w, m);
+            }
+            assert!(zero.mutate(&mut rng).get_weights().iter().all(|&w| w >= 0));
+
+            let child = h_s.crossover(&zero, &mut rng).get_weights();
+            for (i, w) in child.iter().enumerate() {
+                assert!(*w == 0 || *w == h_s.get_weights()[i]);
+            }
+        }
     }
 }
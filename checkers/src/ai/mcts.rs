@@ -0,0 +1,347 @@
+// Monte Carlo tree search with UCT selection. The tree is kept between moves, when asked about a
+// position that is already in it the search carries on from that node.
+
+use super::engine::{Engine, SearchLimits, SearchResult};
+use super::heuristic::Heuristic;
+use super::new_rng;
+use crate::board::{Board, GameOutcome, Player};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+const EXPLORATION: f64 = std::f64::consts::SQRT_2;
+// a playout that runs this long is scored by material instead of played out
+const MAX_PLAYOUT_PLIES: u32 = 200;
+// chance of a heuristic playout picking a random move instead of the best looking one
+const PLAYOUT_EPSILON: f64 = 0.1;
+// the search stops growing the tree past this many nodes
+const MAX_NODES: usize = 1 << 21;
+// there is no search depth, a depth limit gives this many playouts per ply instead
+const ITERATIONS_PER_DEPTH: u64 = 1000;
+// playouts when no limit is given at all, so the search still ends
+const DEFAULT_ITERATIONS: u64 = 10000;
+
+#[derive(Debug, Clone)]
+pub enum Playout {
+    Random,
+    // plays the move the heuristic likes best, with some random moves mixed in
+    Heuristic(Heuristic),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    hash: u64,
+    parent: Option<usize>,
+    // index of the move that leads here from the parent
+    mv: usize,
+    // the player that made that move, the node's wins are counted for them
+    mover: Player,
+    children: Vec<usize>,
+    untried: Vec<usize>,
+    visits: u32,
+    wins: f64,
+}
+
+pub struct Mcts {
+    playout: Playout,
+    nodes: Vec<Node>,
+    root: usize,
+    rng: StdRng,
+}
+
+impl Mcts {
+    pub fn new(playout: Playout) -> Self {
+        Self {
+            playout,
+            nodes: Vec::new(),
+            root: 0,
+            rng: new_rng(None),
+        }
+    }
+
+    // number of nodes in the tree, including the ones kept from earlier moves
+    pub fn tree_size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn new_node(&mut self, b: &Board, parent: Option<usize>, mv: usize) -> usize {
+        let mut untried: Vec<usize> = match b.is_game_over() {
+            Some(_) => Vec::new(),
+            None => (0..b.get_player_info().get_moves().len()).collect(),
+        };
+        untried.shuffle(&mut self.rng);
+        self.nodes.push(Node {
+            hash: b.get_hash(),
+            parent,
+            mv,
+            mover: b.get_current_player().get_other(),
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            wins: 0.0,
+        });
+        self.nodes.len() - 1
+    }
+
+    // moves the root to the node for this position if it is one or two plies below the old root,
+    // otherwise starts a new tree
+    fn set_root(&mut self, b: &Board) {
+        let hash = b.get_hash();
+        let found = if self.nodes.is_empty() {
+            None
+        } else if self.nodes[self.root].hash == hash {
+            Some(self.root)
+        } else {
+            self.nodes[self.root]
+                .children
+                .iter()
+                .flat_map(|&c| self.nodes[c].children.iter())
+                .chain(self.nodes[self.root].children.iter())
+                .copied()
+                .find(|&n| self.nodes[n].hash == hash)
+        };
+        match found {
+            Some(root) => self.keep_subtree(root),
+            None => {
+                self.nodes.clear();
+                self.new_node(b, None, 0);
+            }
+        }
+        self.root = 0;
+    }
+
+    // throws away everything that isn't below the new root, the root ends up at index 0
+    fn keep_subtree(&mut self, root: usize) {
+        let mut kept = Vec::new();
+        let mut queue = VecDeque::from([(root, None)]);
+        while let Some((old, parent)) = queue.pop_front() {
+            let idx = kept.len();
+            let mut node = self.nodes[old].clone();
+            node.parent = parent;
+            for &child in node.children.iter() {
+                queue.push_back((child, Some(idx)));
+            }
+            node.children.clear();
+            if let Some(p) = parent {
+                let p: &mut Node = &mut kept[p];
+                p.children.push(idx);
+            }
+            kept.push(node);
+        }
+        self.nodes = kept;
+    }
+
+    fn select_child(&self, node: usize) -> usize {
+        let log_visits = (self.nodes[node].visits as f64).ln();
+        let uct = |&c: &usize| {
+            let child = &self.nodes[c];
+            let visits = child.visits as f64;
+            child.wins / visits + EXPLORATION * (log_visits / visits).sqrt()
+        };
+        *self.nodes[node]
+            .children
+            .iter()
+            .max_by(|a, b| uct(a).total_cmp(&uct(b)))
+            .expect("Err: selecting from a node without children")
+    }
+
+    fn playout_move(&mut self, b: &mut Board) -> usize {
+        let moves = b.get_player_info().get_moves().len();
+        match &self.playout {
+            Playout::Heuristic(h_s) if !self.rng.gen_bool(PLAYOUT_EPSILON) => {
+                // after the move the opponent is the one to move, so lower is better
+                (0..moves)
+                    .min_by_key(|&mv| {
+                        let undo = b.make_move(mv);
+                        let score = match b.is_game_over() {
+                            Some(GameOutcome::Win(_)) => i32::MIN,
+                            Some(GameOutcome::Draw(_)) => 0,
+                            None => h_s.h(b, true),
+                        };
+                        b.unmake_move(undo);
+                        score
+                    })
+                    .unwrap_or(0)
+            }
+            _ => self.rng.gen_range(0..moves),
+        }
+    }
+
+    // plays the game out and returns the winner, None for a draw
+    fn simulate(&mut self, b: &mut Board) -> Option<Player> {
+        for _ in 0..MAX_PLAYOUT_PLIES {
+            match b.is_game_over() {
+                Some(GameOutcome::Win(winner)) => return Some(winner),
+                Some(GameOutcome::Draw(_)) => return None,
+                None => {}
+            }
+            let mv = self.playout_move(b);
+            b.do_move(mv);
+        }
+        let (mine, theirs) = b.get_pieces();
+        match mine.len().cmp(&theirs.len()) {
+            std::cmp::Ordering::Greater => Some(b.get_current_player()),
+            std::cmp::Ordering::Less => Some(b.get_current_player().get_other()),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    fn iterate(&mut self, root_board: &Board) {
+        let mut b = root_board.clone();
+        let mut node = self.root;
+        while self.nodes[node].untried.is_empty() && !self.nodes[node].children.is_empty() {
+            node = self.select_child(node);
+            b.do_move(self.nodes[node].mv);
+        }
+        if self.nodes.len() < MAX_NODES {
+            if let Some(mv) = self.nodes[node].untried.pop() {
+                b.do_move(mv);
+                let child = self.new_node(&b, Some(node), mv);
+                self.nodes[node].children.push(child);
+                node = child;
+            }
+        }
+
+        let winner = self.simulate(&mut b);
+        let mut current = Some(node);
+        while let Some(n) = current {
+            let n = &mut self.nodes[n];
+            n.visits += 1;
+            n.wins += match winner {
+                Some(p) if p == n.mover => 1.0,
+                Some(_) => 0.0,
+                None => 0.5,
+            };
+            current = n.parent;
+        }
+    }
+}
+
+impl Default for Mcts {
+    fn default() -> Self {
+        Self::new(Playout::Random)
+    }
+}
+
+impl Engine for Mcts {
+    fn name(&self) -> String {
+        String::from("MCTS")
+    }
+
+    fn choose_move(&mut self, b: &Board, limits: SearchLimits) -> SearchResult {
+        if b.get_player_info().get_moves().len() == 1 {
+            return SearchResult::new(0);
+        }
+        self.set_root(b);
+
+        // the node limit is the number of playouts
+        let max_iterations = limits
+            .nodes
+            .or(limits.depth.map(|d| d as u64 * ITERATIONS_PER_DEPTH))
+            .or(limits.is_unlimited().then_some(DEFAULT_ITERATIONS));
+        let start = Instant::now();
+        let mut iterations = 0;
+        while limits.time.is_none_or(|time| start.elapsed() < time)
+            && max_iterations.is_none_or(|max| iterations < max)
+            && !limits.is_stopped()
+        {
+            self.iterate(b);
+            iterations += 1;
+        }
+        #[cfg(feature = "enable_print")]
+        {
+            eprintln!(
+                "MCTS ran {} iterations, the tree has {} nodes",
+                iterations,
+                self.nodes.len()
+            );
+        }
+
+        let best = self.nodes[self.root]
+            .children
+            .iter()
+            .max_by_key(|&&c| self.nodes[c].visits)
+            .map(|&c| self.nodes[c].mv)
+            .unwrap_or(0);
+        SearchResult {
+            pv: vec![best],
+            nodes: iterations,
+            time: start.elapsed(),
+            ..SearchResult::new(best)
+        }
+    }
+
+    // with the same seed and a node limit the same moves are chosen every time
+    fn set_seed(&mut self, seed: u64) {
+        self.rng = new_rng(Some(seed));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_avoids_losing_move() {
+        // moving 1-6 lets red take the last black piece, 1-5 is safe
+        let b = Board::from_fen("B:W10:B1").unwrap();
+        for playout in [
+            Playout::Random,
+            Playout::Heuristic(Heuristic::default_new()),
+        ] {
+            let mut mcts = Mcts::new(playout);
+            let mv = mcts
+                .choose_move(&b, SearchLimits::from_nodes(2000))
+                .best_move;
+            assert_eq!(b.get_player_info().get_moves()[mv].notation(), "1-5");
+        }
+    }
+
+    #[test]
+    fn test_tree_reuse() {
+        let mut b = Board::default();
+        let mut mcts = Mcts::new(Playout::Random);
+        let mv = mcts
+            .choose_move(&b, SearchLimits::from_nodes(3000))
+            .best_move;
+        b.do_move(mv);
+        b.do_move(0);
+
+        // the reply was searched below the chosen move so part of the tree is kept
+        mcts.set_root(&b);
+        assert!(mcts.tree_size() > 1);
+        assert_eq!(mcts.nodes[0].hash, b.get_hash());
+        assert!(mcts.nodes[0].visits > 0);
+        for &c in mcts.nodes[0].children.iter() {
+            assert_eq!(mcts.nodes[c].parent, Some(0));
+        }
+
+        // an unrelated position starts from scratch
+        mcts.set_root(&Board::from_fen("B:W10:B1").unwrap());
+        assert_eq!(mcts.tree_size(), 1);
+    }
+
+    #[test]
+    fn test_depth_and_no_limit() {
+        let b = Board::default();
+        let mut mcts = Mcts::default();
+        let result = mcts.choose_move(&b, SearchLimits::from_depth(2));
+        assert_eq!(result.nodes, 2 * ITERATIONS_PER_DEPTH);
+        let result = Mcts::default().choose_move(&b, SearchLimits::default());
+        assert_eq!(result.nodes, DEFAULT_ITERATIONS);
+    }
+
+    #[test]
+    fn test_seed() {
+        let b = Board::default();
+        let run = || {
+            let mut mcts = Mcts::default();
+            mcts.set_seed(7);
+            let mv = mcts
+                .choose_move(&b, SearchLimits::from_nodes(500))
+                .best_move;
+            (mv, mcts.tree_size(), mcts.nodes[mcts.root].wins)
+        };
+        assert_eq!(run(), run());
+    }
+}
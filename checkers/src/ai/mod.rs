@@ -1,14 +1,217 @@
+pub mod book;
+pub mod engine;
 pub mod heuristic;
+pub mod mcts;
+pub mod tablebase;
+pub mod transposition;
+pub mod tuning;
 mod visualize_tree_ai;
-use crate::board::{Board, Moves};
+use crate::board::{square_number, Board, GameOutcome, Moves, Player};
+use engine::{SearchLimits, SearchOptions, SearchResult};
 use heuristic::Heuristic;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "tree_debug")]
 use std::fs::OpenOptions;
-use std::i32::MAX;
+#[cfg(feature = "tree_debug")]
 use std::io::Write;
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+use tablebase::{Tablebase, TbValue};
+use transposition::{Bound, TranspositionTable};
 use visualize_tree_ai::{RTTree, Tree};
+const MAX: i32 = i32::MAX;
 const MIN: i32 = -MAX;
+const DRAW: i32 = 0;
+// any score past this is a win or loss found by the search rather than a heuristic value
+const WIN_BOUND: i32 = MAX - 1000;
+// most nodes a single quiescence search may visit before the leaf is evaluated as it stands
+const QUIESCENCE_NODE_LIMIT: u32 = 5000;
+// half width of the first window tried around the last iteration's score, half a man because the
+// score swings a lot between odd and even depths
+const ASPIRATION_WINDOW: i32 = 5000;
+// depth searched when no limit is given at all, a draw is never proven so it wouldn't stop
+const DEFAULT_DEPTH: u32 = 10;
+// once a history entry passes this the whole table is halved, so it can't overflow and old
+// cutoffs count for less than new ones
+const HISTORY_LIMIT: u32 = 1 << 24;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchStats {
+    // leaves evaluated by the heuristic, including the ones at the end of a quiescence search
+    pub leaves: u64,
+    // leaves at the depth limit that still had a capture to make and were searched further
+    pub quiescence_searches: u64,
+    pub quiescence_nodes: u64,
+    // quiescence searches that ran into QUIESCENCE_NODE_LIMIT
+    pub quiescence_cutoffs: u64,
+    // furthest a quiescence search went past the depth limit
+    pub max_quiescence_ply: u32,
+    // positions visited by the main search, the ones in quiescence searches are counted separately
+    pub nodes: u64,
+    pub cutoffs: u64,
+    // cutoffs caused by the first move tried, the closer to all of them the better the ordering
+    pub first_move_cutoffs: u64,
+    // moves that beat the null window and had to be searched again with the full one
+    pub pvs_researches: u64,
+    // depths searched again because the score fell outside the aspiration window
+    pub aspiration_researches: u64,
+    // positions scored from the endgame tables instead of being searched
+    pub tablebase_hits: u64,
+}
+
+// Every random choice of the AI is made with one of these. Made from a seed the choices come out
+// the same every time, so a game or a training run can be played again exactly.
+pub fn new_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+// a move as its start and end square, so it can be recognised in other positions
+type MoveKey = (usize, usize);
+
+fn move_key(mv: &Moves) -> MoveKey {
+    (
+        square_number(mv.get_start_loc()) - 1,
+        square_number(mv.get_end_loc()) - 1,
+    )
+}
+
+struct SearchContext<'a> {
+    start: Instant,
+    deadline: Option<Instant>,
+    node_limit: Option<u64>,
+    depth_limit: Option<u32>,
+    // set by whoever started the search to stop it early
+    external_stop: Option<&'a AtomicBool>,
+    info: Option<&'a Sender<SearchResult>>,
+    h_s: &'a Heuristic,
+    // shared by all threads searching the same move
+    tt: &'a TranspositionTable,
+    // set once the main thread is done so the helper threads stop too
+    stop: &'a AtomicBool,
+    stats: SearchStats,
+    // two quiet moves per ply that caused a cutoff, tried early in sibling positions
+    killers: Vec<[Option<MoveKey>; 2]>,
+    // how much each quiet move has caused cutoffs, indexed by start and end square
+    history: [[u32; 32]; 32],
+    // pv_table[ply] is the best line found from that ply in the current iteration
+    pv_table: Vec<Vec<usize>>,
+    // the best line of the last finished iteration, searched first by the next one
+    prev_pv: Vec<usize>,
+    // true while the search is still going down prev_pv
+    follow_pv: bool,
+    // adds a little randomness to every evaluation so equal moves aren't always played the same,
+    // None for the plain evaluation
+    noise: Option<StdRng>,
+    // root moves left out of the search, the lines multi-PV analysis already found
+    root_excluded: Vec<usize>,
+    tablebase: Option<&'a Tablebase>,
+}
+
+impl<'a> SearchContext<'a> {
+    fn new(
+        limits: &'a SearchLimits,
+        h_s: &'a Heuristic,
+        tt: &'a TranspositionTable,
+        stop: &'a AtomicBool,
+    ) -> Self {
+        let start = Instant::now();
+        Self {
+            start,
+            deadline: limits.time.map(|time| start + time - safety_margin(time)),
+            node_limit: limits.nodes,
+            depth_limit: limits
+                .depth
+                .or(limits.is_unlimited().then_some(DEFAULT_DEPTH)),
+            external_stop: limits.stop.as_deref(),
+            info: limits.info.as_ref(),
+            h_s,
+            tt,
+            stop,
+            stats: SearchStats::default(),
+            killers: Vec::new(),
+            history: [[0; 32]; 32],
+            pv_table: Vec::new(),
+            prev_pv: Vec::new(),
+            follow_pv: false,
+            noise: None,
+            root_excluded: Vec::new(),
+            tablebase: None,
+        }
+    }
+
+    // makes room for an iteration that searches `depth` plies
+    fn start_iteration(&mut self, depth: u32) {
+        let plies = depth as usize + 1;
+        if self.killers.len() < plies {
+            self.killers.resize(plies, [None; 2]);
+        }
+        self.pv_table = vec![Vec::new(); plies];
+        self.follow_pv = true;
+    }
+
+    fn update_pv(&mut self, ply: usize, mv: usize) {
+        let (this, deeper) = self.pv_table.split_at_mut(ply + 1);
+        this[ply].clear();
+        this[ply].push(mv);
+        if let Some(line) = deeper.first() {
+            this[ply].extend_from_slice(line);
+        }
+    }
+
+    // a quiet move refuted the position, remember it for the positions around it
+    fn record_cutoff(&mut self, state: &Board, mv: usize, ply: usize, depth: u32, first: bool) {
+        self.stats.cutoffs += 1;
+        if first {
+            self.stats.first_move_cutoffs += 1;
+        }
+        let move_obj = &state.get_player_info().get_moves()[mv];
+        if move_obj.is_jump() {
+            return;
+        }
+        let key = move_key(move_obj);
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(key) {
+            killers[1] = killers[0];
+            killers[0] = Some(key);
+        }
+        let (start, end) = key;
+        self.history[start][end] += depth * depth;
+        if self.history[start][end] > HISTORY_LIMIT {
+            self.history
+                .iter_mut()
+                .flatten()
+                .for_each(|entry| *entry /= 2);
+        }
+    }
+}
+
+// wins and losses are scored by their distance from the root, the table stores them by their
+// distance from the stored position so they stay correct when it is reached at another ply
+fn score_to_tt(score: i32, ply: u32) -> i32 {
+    if score > WIN_BOUND {
+        score + ply as i32
+    } else if score < -WIN_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: u32) -> i32 {
+    if score > WIN_BOUND {
+        score - ply as i32
+    } else if score < -WIN_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
 
 #[derive(Clone, Copy)]
 enum ABResult {
@@ -33,18 +236,225 @@ impl ABResult {
     }
 }
 
-pub fn predict_move(b: Board, time_limit: u32, h_s_param: Option<Heuristic>) -> usize {
-    // let player_info_rc = b.get_player_info();
-    // let player_info = player_info_rc.borrow();
-    let player_info = b.get_player_info();
+pub fn predict_move(b: Board, limits: SearchLimits, h_s_param: Option<Heuristic>) -> SearchResult {
+    predict_move_threaded(b, limits, h_s_param, &SearchOptions::default())
+}
 
-    if player_info.get_moves().len() == 1 {
+// Multi-PV: the best `lines` root moves, best first. Every depth searches the root once per line,
+// each time without the moves already found, so each line gets an exact score of its own. The
+// lines are the ones of the last depth that was completed for all of them.
+pub fn analyze(
+    b: Board,
+    limits: SearchLimits,
+    h_s_param: Option<Heuristic>,
+    lines: usize,
+    tablebase: Option<&Tablebase>,
+) -> Vec<SearchResult> {
+    let lines = lines.min(b.get_player_info().get_moves().len());
+    let h_s = h_s_param.unwrap_or_default();
+    let tt = TranspositionTable::default();
+    let stop = AtomicBool::new(false);
+    let mut ctx = SearchContext::new(&limits, &h_s, &tt, &stop);
+    // the scores are compared between lines, so they are the plain evaluation without noise
+    ctx.tablebase = tablebase;
+    let mut state = b.clone();
+    let mut results: Vec<SearchResult> = Vec::new();
+    let mut d = 1;
+    'deepening: while lines > 0 && ctx.depth_limit.is_none_or(|max| d <= max) {
+        let mut found = Vec::new();
+        let mut finished = true;
+        ctx.root_excluded.clear();
+        for line in 0..lines {
+            ctx.start_iteration(d);
+            // the same line of the last depth is searched first, unless its move is already taken
+            ctx.prev_pv = results
+                .get(line)
+                .map(|r| r.pv.clone())
+                .filter(|pv| !ctx.root_excluded.contains(&pv[0]))
+                .unwrap_or_default();
+            let (score, mv) = match negamax(&mut state, d, &d, MIN, MAX, &mut ctx, &mut None) {
+                (score, ABResult::DepthReached(Some(mv))) => (score, mv),
+                (score, ABResult::Finished(Some(mv))) => (score, mv),
+                _ => {
+                    // stopped before the first depth was done, the lines found so far are all there is
+                    if results.is_empty() {
+                        results = found;
+                    }
+                    break 'deepening;
+                }
+            };
+            finished &= score.abs() > WIN_BOUND;
+            ctx.root_excluded.push(mv);
+            found.push(SearchResult {
+                score: Some(score),
+                pv: ctx.pv_table[0].clone(),
+                depth: d,
+                ..SearchResult::new(mv)
+            });
+        }
+        // a later line can still edge out an earlier one through what the table kept between them
+        found.sort_by_key(|r| std::cmp::Reverse(r.score));
+        results = found;
+        #[cfg(feature = "enable_print")]
+        {
+            eprintln!(
+                "Analyzed {} lines to depth {:?}, current time is {:?}",
+                lines,
+                d,
+                ctx.start.elapsed().as_millis()
+            );
+        }
+        if finished {
+            break;
+        }
+        d += 1;
+    }
+    ctx.root_excluded.clear();
+    for result in results.iter_mut() {
+        result.nodes = ctx.stats.nodes + ctx.stats.quiescence_nodes;
+        result.time = ctx.start.elapsed();
+    }
+    results
+}
+
+// time kept back to get out of the search and play the move, at most a tenth of the time given
+fn safety_margin(time: Duration) -> Duration {
+    (time / 10).min(Duration::from_millis(100))
+}
+
+// Lazy SMP: every thread searches the same position and they help each other only through the
+// shared transposition table. The move is the main thread's, the helpers are stopped when it is
+// done.
+pub fn predict_move_threaded(
+    b: Board,
+    limits: SearchLimits,
+    h_s_param: Option<Heuristic>,
+    options: &SearchOptions,
+) -> SearchResult {
+    let SearchOptions {
+        threads,
+        tablebase,
+        book,
+        seed,
+        noise,
+    } = *options;
+    // without a move the player to move has lost, there is nothing to search
+    if b.get_player_info().get_moves().is_empty() {
+        return SearchResult::resign();
+    }
+    if b.get_player_info().get_moves().len() == 1 {
         // if there is only one move do it
-        return 0;
+        return SearchResult::new(0);
+    }
+    if let Some(mv) = book.and_then(|book| book.choose(&b, &mut new_rng(seed))) {
+        #[cfg(feature = "enable_print")]
+        {
+            eprintln!("Playing a book move");
+        }
+        return SearchResult {
+            pv: vec![mv],
+            ..SearchResult::new(mv)
+        };
+    }
+    if let Some(result) = tablebase.and_then(|tb| tablebase_result(&b, tb)) {
+        if let Some(info) = &limits.info {
+            let _ = info.send(result.clone());
+        }
+        return result;
     }
-    drop(player_info);
+    #[cfg(feature = "enable_print")]
+    {
+        eprintln!("Starting AB/P on {} threads", threads.max(1));
+    }
+    let h_s = h_s_param.unwrap_or_default();
+    let tt = TranspositionTable::default();
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let helpers: Vec<_> = (1..threads)
+            .map(|id| {
+                let (b, limits, h_s, tt, stop) = (&b, &limits, &h_s, &tt, &stop);
+                scope.spawn(move || {
+                    let mut ctx = SearchContext::new(limits, h_s, tt, stop);
+                    // only the main thread's depth counts
+                    ctx.depth_limit = None;
+                    ctx.tablebase = tablebase;
+                    // each helper gets noise of its own
+                    ctx.noise =
+                        noise.then(|| new_rng(seed.map(|seed| seed.wrapping_add(id as u64))));
+                    helper_search(b, id, &mut ctx);
+                    ctx.stats.nodes + ctx.stats.quiescence_nodes
+                })
+            })
+            .collect();
+        let mut ctx = SearchContext::new(&limits, &h_s, &tt, &stop);
+        ctx.tablebase = tablebase;
+        ctx.noise = noise.then(|| new_rng(seed));
+        let mut result = iterative_deepening(&b, &mut ctx);
+        stop.store(true, Ordering::Relaxed);
+        for helper in helpers {
+            result.nodes += helper.join().expect("Err: search thread panicked");
+        }
+        result.time = ctx.start.elapsed();
+        result
+    })
+}
+
+// A position the tables hold needs no search, the move is the one they give and the line is
+// followed through them until the game is over.
+fn tablebase_result(b: &Board, tb: &Tablebase) -> Option<SearchResult> {
+    let start = Instant::now();
+    let (best, value) = tb.best_move(b)?;
+    let mut pv = vec![best];
+    if value != TbValue::Draw {
+        let mut state = b.clone();
+        state.make_move(best);
+        while let Some((mv, _)) = tb.best_move(&state) {
+            pv.push(mv);
+            state.make_move(mv);
+        }
+    }
+    Some(SearchResult {
+        score: Some(tablebase_score(value, 0)),
+        pv,
+        time: start.elapsed(),
+        ..SearchResult::new(best)
+    })
+}
+
+// the same scores the search gives a win or loss found `ply` plies from the root
+fn tablebase_score(value: TbValue, ply: u32) -> i32 {
+    match value {
+        TbValue::Win(plies) => MAX - (ply + plies) as i32,
+        TbValue::Loss(plies) => MIN + (ply + plies) as i32,
+        TbValue::Draw => DRAW,
+    }
+}
+
+// A helper thread of a parallel search. Every other helper starts a depth ahead so the threads
+// spread out over different depths, what they find is only passed on through the table.
+fn helper_search(b: &Board, id: usize, ctx: &mut SearchContext) {
+    let mut state = b.clone();
+    let mut prev_score = None;
+    let mut d = 1 + (id % 2) as u32;
+    loop {
+        ctx.start_iteration(d);
+        match aspiration_search(&mut state, d, prev_score, ctx, &mut None) {
+            (score, ABResult::DepthReached(_)) => {
+                prev_score = Some(score);
+                ctx.prev_pv = ctx.pv_table[0].clone();
+            }
+            _ => return,
+        }
+        d += 1;
+    }
+}
+
+#[cfg_attr(not(feature = "tree_debug"), allow(unused_variables, unused_mut))]
+fn iterative_deepening(b: &Board, ctx: &mut SearchContext) -> SearchResult {
     let mut d = 1;
-    let mut mv = 0;
+    // what the last completed depth found
+    let mut result = SearchResult::new(0);
+    let mut prev_score = None;
 
     // this creates a tree in debug mode
     // This match statment should always be compiled out
@@ -59,14 +469,9 @@ pub fn predict_move(b: Board, time_limit: u32, h_s_param: Option<Heuristic>) ->
         })),
         false => Option::None,
     };
-    #[cfg(feature = "enable_print")]
-    {
-        println!("Starting AB/P");
-    }
-    let h_s = h_s_param.unwrap_or(Heuristic::default_new());
-    let now = SystemTime::now();
-    let time_limit = ((time_limit as u128) * 1000) - 100;
+    let mut state = b.clone();
     loop {
+        ctx.start_iteration(d);
         let mut inner_tree: Option<Tree<RTTree>> = match cfg!(feature = "tree_debug") {
             true => Option::Some(Tree::new(RTTree {
                 h_val: 0,
@@ -78,22 +483,13 @@ pub fn predict_move(b: Board, time_limit: u32, h_s_param: Option<Heuristic>) ->
             })),
             false => Option::None,
         };
-        let (_, v) = max_value(
-            b.clone(),
-            d,
-            &d,
-            MIN,
-            MAX,
-            time_limit,
-            &now,
-            &h_s,
-            &mut inner_tree,
-        );
+        let (score, v) = aspiration_search(&mut state, d, prev_score, ctx, &mut inner_tree);
         match v {
             ABResult::Finished(value) => {
                 #[cfg(feature = "enable_print")]
                 {
-                    println!("Found Bottom Depth: {:?}", d);
+                    eprintln!("Found Bottom Depth: {:?}", d);
+                    print_stats(&ctx.stats);
                 }
                 #[cfg(feature = "tree_debug")]
                 {
@@ -111,16 +507,23 @@ pub fn predict_move(b: Board, time_limit: u32, h_s_param: Option<Heuristic>) ->
                     )
                     .expect("Error Writting");
                 }
-                return value.expect("Err: Finished without value");
+                result.best_move = value.expect("Err: Finished without value");
+                result.score = Some(score);
+                result.pv = ctx.pv_table[0].clone();
+                result.depth = d;
+                result.nodes = ctx.stats.nodes + ctx.stats.quiescence_nodes;
+                report(ctx, &result);
+                return result;
             }
             ABResult::TimeLimitExpired => {
                 #[cfg(feature = "enable_print")]
                 {
-                    println!(
+                    eprintln!(
                         "Time limit expired in depth {:?}, current time is {:?}",
                         d,
-                        now.elapsed().expect("Err: Invalid Sys time").as_millis()
+                        ctx.start.elapsed().as_millis()
                     );
+                    print_stats(&ctx.stats);
                 }
                 #[cfg(feature = "tree_debug")]
                 {
@@ -138,32 +541,41 @@ pub fn predict_move(b: Board, time_limit: u32, h_s_param: Option<Heuristic>) ->
                     )
                     .expect("Error Writting");
                 }
-                return mv;
+                result.nodes = ctx.stats.nodes + ctx.stats.quiescence_nodes;
+                return result;
             }
             ABResult::DepthReached(value) => {
                 #[cfg(feature = "enable_print")]
                 {
-                    println!(
+                    eprintln!(
                         "Finished depth {:?}, current time is {:?}",
                         d,
-                        now.elapsed().expect("Err: Invalid Sys time").as_millis()
+                        ctx.start.elapsed().as_millis()
                     );
+                    print_stats(&ctx.stats);
                 }
-                mv = value.expect("Err: No DepthReached without value");
+                result.best_move = value.expect("Err: No DepthReached without value");
+                result.score = Some(score);
+                result.pv = ctx.pv_table[0].clone();
+                result.depth = d;
+                report(ctx, &result);
+                prev_score = Some(score);
+                ctx.prev_pv = ctx.pv_table[0].clone();
                 #[cfg(feature = "tree_debug")]
                 {
                     tree = inner_tree;
                 }
             }
             ABResult::Inital => {
-                if check_time_limit(time_limit, &now) {
+                if check_time_limit(ctx) {
                     #[cfg(feature = "enable_print")]
                     {
-                        println!(
+                        eprintln!(
                             "Time limit expired in depth {:?}, current time is {:?}",
                             d,
-                            now.elapsed().expect("Err: Invalid Sys time").as_millis()
+                            ctx.start.elapsed().as_millis()
                         );
+                        print_stats(&ctx.stats);
                     }
                     #[cfg(feature = "tree_debug")]
                     {
@@ -177,108 +589,402 @@ pub fn predict_move(b: Board, time_limit: u32, h_s_param: Option<Heuristic>) ->
                         write!(f, "{}", serde_json::to_string(&inner_tree).unwrap())
                             .expect("Error Writting");
                     }
-                    return mv;
+                    result.nodes = ctx.stats.nodes + ctx.stats.quiescence_nodes;
+                    return result;
                 }
                 #[cfg(feature = "enable_print")]
                 {
-                    println!(
+                    eprintln!(
                         "Finished depth {:?}, current time is {:?}",
                         d,
-                        now.elapsed().expect("Err: Invalid Sys time").as_millis()
+                        ctx.start.elapsed().as_millis()
                     );
                 }
                 #[cfg(feature = "tree_debug")]
                 {
                     tree = inner_tree;
                 }
-                mv = 0;
+                result = SearchResult::new(0);
             }
         };
+        if ctx.depth_limit.is_some_and(|max| d >= max) {
+            #[cfg(feature = "enable_print")]
+            {
+                eprintln!("Reached the depth limit {}", d);
+                print_stats(&ctx.stats);
+            }
+            result.nodes = ctx.stats.nodes + ctx.stats.quiescence_nodes;
+            return result;
+        }
         d += 1;
     }
 }
 
-fn check_time_limit(time_limit: u128, now: &SystemTime) -> bool {
-    now.elapsed().expect("Err: Invalid Sys time").as_millis() >= time_limit
+// hands a finished depth to whoever is watching the search
+fn report(ctx: &SearchContext, result: &SearchResult) {
+    if let Some(info) = ctx.info {
+        // nobody listening any more is not a reason to stop searching
+        let _ = info.send(SearchResult {
+            nodes: ctx.stats.nodes + ctx.stats.quiescence_nodes,
+            time: ctx.start.elapsed(),
+            ..result.clone()
+        });
+    }
+}
+
+#[cfg(feature = "enable_print")]
+fn print_stats(stats: &SearchStats) {
+    eprintln!(
+        "Searched {} nodes, {} cutoffs with {:.1}% on the first move, {} null window and {} aspiration re-searches",
+        stats.nodes,
+        stats.cutoffs,
+        100.0 * stats.first_move_cutoffs as f64 / stats.cutoffs.max(1) as f64,
+        stats.pvs_researches,
+        stats.aspiration_researches
+    );
+    eprintln!(
+        "Evaluated {} leaves, {} needed a quiescence search ({} nodes, {} hit the node limit, {} plies at most)",
+        stats.leaves,
+        stats.quiescence_searches,
+        stats.quiescence_nodes,
+        stats.quiescence_cutoffs,
+        stats.max_quiescence_ply
+    );
+    if stats.tablebase_hits > 0 {
+        eprintln!(
+            "Scored {} positions from the tablebase",
+            stats.tablebase_hits
+        );
+    }
 }
 
+fn check_time_limit(ctx: &SearchContext) -> bool {
+    ctx.stop.load(Ordering::Relaxed)
+        || ctx.external_stop.is_some_and(|s| s.load(Ordering::Relaxed))
+        || ctx.deadline.is_some_and(|d| Instant::now() >= d)
+        || ctx
+            .node_limit
+            .is_some_and(|n| ctx.stats.nodes + ctx.stats.quiescence_nodes >= n)
+}
+
+// Scores are from the point of view of the player to move, the parent negates them. The window
+// only matters for the quiescence search, its score is a bound when it falls outside of it.
 fn is_terminal(
-    state: &Board,
+    state: &mut Board,
     depth: u32,
     max_depth: &u32,
-    time_limit: u128,
-    now: &SystemTime,
-    is_max: bool,
-    h_s: &Heuristic,
+    alpha: i32,
+    beta: i32,
+    ctx: &mut SearchContext,
 ) -> Result<(i32, ABResult), ()> {
-    if check_time_limit(time_limit, now) {
+    if check_time_limit(ctx) {
         return Result::Ok((0, ABResult::TimeLimitExpired));
     }
-    if let Some(winner) = state.is_game_over() {
-        if (is_max && winner == state.get_current_player())
-            || (!is_max && winner != state.get_current_player())
-        {
-            return Result::Ok((MAX - (max_depth - depth) as i32, ABResult::Finished(None)));
+    let ply = max_depth - depth;
+    match state.is_game_over() {
+        Some(GameOutcome::Win(winner)) => {
+            return Result::Ok((win_score(state, winner, ply), ABResult::Finished(None)));
+        }
+        // A draw is only final for this line, a deeper search may still find something better.
+        // The root is searched anyway, like for the tablebase below.
+        Some(GameOutcome::Draw(_)) if ply > 0 => {
+            return Result::Ok((DRAW, ABResult::DepthReached(None)))
         }
-        return Result::Ok((MIN + (max_depth - depth) as i32, ABResult::Finished(None)));
+        _ => {}
+    }
+    // not at the root, which needs a move and not just a score
+    if let Some(value) = ctx
+        .tablebase
+        .filter(|_| ply > 0)
+        .and_then(|tb| tb.probe(state))
+    {
+        ctx.stats.tablebase_hits += 1;
+        return Result::Ok(match value {
+            // like any other draw a deeper search can't change it, but it isn't a proven result
+            TbValue::Draw => (DRAW, ABResult::DepthReached(None)),
+            _ => (tablebase_score(value, ply), ABResult::Finished(None)),
+        });
     }
     if depth == 0 {
-        let mut rng = rand::thread_rng();
-        return Result::Ok((
-            h_s.h(&state, is_max) + rng.gen_range(-9..9),
-            ABResult::DepthReached(None),
-        ));
+        if state.get_player_info().get_can_jump() {
+            ctx.stats.quiescence_searches += 1;
+            let mut nodes_left = QUIESCENCE_NODE_LIMIT;
+            let v = quiesce(state, *max_depth, 0, alpha, beta, &mut nodes_left, ctx);
+            return Result::Ok((v, ABResult::DepthReached(None)));
+        }
+        return Result::Ok((evaluate(state, ctx), ABResult::DepthReached(None)));
     }
     Result::Err(())
 }
 
-fn max_value(
-    state: Board,
+// wins are worth more the sooner they happen
+fn win_score(state: &Board, winner: Player, ply: u32) -> i32 {
+    if winner == state.get_current_player() {
+        MAX - ply as i32
+    } else {
+        MIN + ply as i32
+    }
+}
+
+fn evaluate(state: &Board, ctx: &mut SearchContext) -> i32 {
+    ctx.stats.leaves += 1;
+    let v = ctx.h_s.h(state, true);
+    match ctx.noise.as_mut() {
+        Some(rng) => v + rng.gen_range(-9..9),
+        None => v,
+    }
+}
+
+// Keeps playing out captures past the depth limit so a leaf is only evaluated once nothing is
+// left to take. Captures are forced so the side to move can't stand pat.
+fn quiesce(
+    state: &mut Board,
+    ply: u32,
+    q_ply: u32,
+    mut alpha: i32,
+    beta: i32,
+    nodes_left: &mut u32,
+    ctx: &mut SearchContext,
+) -> i32 {
+    ctx.stats.quiescence_nodes += 1;
+    ctx.stats.max_quiescence_ply = ctx.stats.max_quiescence_ply.max(q_ply);
+    match state.is_game_over() {
+        Some(GameOutcome::Win(winner)) => return win_score(state, winner, ply),
+        Some(GameOutcome::Draw(_)) => return DRAW,
+        None => {}
+    }
+    if !state.get_player_info().get_can_jump() {
+        return evaluate(state, ctx);
+    }
+    if *nodes_left == 0 {
+        ctx.stats.quiescence_cutoffs += 1;
+        return evaluate(state, ctx);
+    }
+    *nodes_left -= 1;
+
+    let mut v = MIN;
+    for mv in 0..state.get_player_info().get_moves().len() {
+        let undo = state.make_move(mv);
+        let v2 = -quiesce(state, ply + 1, q_ply + 1, -beta, -alpha, nodes_left, ctx);
+        state.unmake_move(undo);
+        v = v.max(v2);
+        alpha = alpha.max(v);
+        if alpha >= beta {
+            break;
+        }
+    }
+    v
+}
+
+// Looks the position up in the transposition table. Returns the stored score if it is deep
+// enough to decide this node, otherwise the stored best move to try first.
+fn probe_tt(
+    state: &Board,
+    depth: u32,
+    max_depth: &u32,
+    alpha: i32,
+    beta: i32,
+    ctx: &SearchContext,
+) -> Result<(i32, ABResult), Option<usize>> {
+    let ply = max_depth - depth;
+    let entry = match ctx.tt.probe(state.get_hash()) {
+        Some(entry) => entry,
+        None => return Result::Err(None),
+    };
+    let tt_move = entry
+        .best_move
+        .map(|m| m as usize)
+        .filter(|&m| m < state.get_player_info().get_moves().len());
+    // the root always has to search to find out which move to play
+    if ply == 0 || (entry.depth as u32) < depth {
+        return Result::Err(tt_move);
+    }
+    let score = score_from_tt(entry.score, ply);
+    match entry.bound {
+        Bound::Exact => Result::Ok((score, ABResult::DepthReached(None))),
+        Bound::Lower if score >= beta => Result::Ok((score, ABResult::DepthReached(None))),
+        Bound::Upper if score <= alpha => Result::Ok((score, ABResult::DepthReached(None))),
+        _ => Result::Err(tt_move),
+    }
+}
+
+// The move of the last iteration's best line while still on it, then the table's best move. Jumps
+// follow by the number of pieces taken, quiet moves by the killers and then the history table.
+fn move_order(
+    state: &Board,
+    tt_move: Option<usize>,
+    ply: usize,
+    ctx: &mut SearchContext,
+) -> Vec<usize> {
+    let moves = state.get_player_info().get_moves();
+    let pv_move = match ctx.follow_pv {
+        true => ctx.prev_pv.get(ply).copied().filter(|&m| m < moves.len()),
+        false => None,
+    };
+    if pv_move.is_none() {
+        ctx.follow_pv = false;
+    }
+    let killers = ctx.killers[ply];
+    let score = |m: usize| -> u64 {
+        let move_obj = &moves[m];
+        if Some(m) == pv_move {
+            return u64::MAX;
+        }
+        if Some(m) == tt_move {
+            return u64::MAX - 1;
+        }
+        if move_obj.is_jump() {
+            return move_obj.get_jump_path().len() as u64;
+        }
+        let key = move_key(move_obj);
+        if killers[0] == Some(key) {
+            return u64::MAX - 2;
+        }
+        if killers[1] == Some(key) {
+            return u64::MAX - 3;
+        }
+        let (start, end) = key;
+        ctx.history[start][end] as u64
+    };
+    let mut order: Vec<usize> = (0..moves.len()).collect();
+    // stable so equal moves stay in generation order
+    order.sort_by_key(|&m| std::cmp::Reverse(score(m)));
+    order
+}
+
+// Searches with a window around the last iteration's score, which cuts more of the tree when
+// the score doesn't move much. If the real score falls outside the window is widened and the
+// depth searched again.
+fn aspiration_search(
+    state: &mut Board,
+    depth: u32,
+    prev_score: Option<i32>,
+    ctx: &mut SearchContext,
+    tree: &mut Option<Tree<RTTree>>,
+) -> (i32, ABResult) {
+    let mut delta = ASPIRATION_WINDOW;
+    let (mut alpha, mut beta) = match prev_score {
+        Some(score) if score.abs() < WIN_BOUND => (score - delta, score + delta),
+        _ => (MIN, MAX),
+    };
+    loop {
+        let (v, result) = negamax(state, depth, &depth, alpha, beta, ctx, tree);
+        let failed_low = v <= alpha && alpha > MIN;
+        let failed_high = v >= beta && beta < MAX;
+        if matches!(result, ABResult::TimeLimitExpired) || !(failed_low || failed_high) {
+            return (v, result);
+        }
+        ctx.stats.aspiration_researches += 1;
+        delta = delta.saturating_mul(4);
+        if failed_low {
+            alpha = v.saturating_sub(delta).max(MIN);
+        }
+        if failed_high {
+            beta = v.saturating_add(delta);
+        }
+        if v.abs() >= WIN_BOUND {
+            (alpha, beta) = (MIN, MAX);
+        }
+        ctx.start_iteration(depth);
+        #[cfg(feature = "tree_debug")]
+        {
+            tree.as_mut().unwrap().clear();
+        }
+    }
+}
+
+// Principal variation search: the first move gets the full window, the rest are only checked
+// with a null window to prove they are worse and searched again if they turn out better.
+#[cfg_attr(not(feature = "tree_debug"), allow(unused_variables))]
+fn negamax(
+    state: &mut Board,
     depth: u32,
     max_depth: &u32,
     mut alpha: i32,
     beta: i32,
-    time_limit: u128,
-    now: &SystemTime,
-    h_s: &Heuristic,
+    ctx: &mut SearchContext,
     tree: &mut Option<Tree<RTTree>>,
 ) -> (i32, ABResult) {
-    match is_terminal(&state, depth, max_depth, time_limit, now, true, h_s) {
+    let ply = (max_depth - depth) as usize;
+    ctx.stats.nodes += 1;
+    // cleared even when returning early so the parent never picks up a stale line
+    ctx.pv_table[ply].clear();
+    if let Result::Ok(r) = is_terminal(state, depth, max_depth, alpha, beta, ctx) {
+        return r;
+    };
+    let tt_move = match probe_tt(state, depth, max_depth, alpha, beta, ctx) {
         Result::Ok(r) => return r,
-        Result::Err(_) => {}
+        Result::Err(tt_move) => tt_move,
     };
 
+    let alpha_orig = alpha;
     let mut v = MIN;
     let mut mv = ABResult::Inital;
-    for p_mv in 0..state.get_player_info().get_moves().len() {
+    let mut best_mv = None;
+    let mut order = move_order(state, tt_move, ply, ctx);
+    if ply == 0 {
+        order.retain(|m| !ctx.root_excluded.contains(m));
+    }
+    for (i, &p_mv) in order.iter().enumerate() {
         let mut inner_tree: Option<Tree<RTTree>> = match cfg!(feature = "tree_debug") {
             true => Option::Some(Tree::new(RTTree {
                 h_val: 0,
                 mv: state.get_player_info().get_moves()[p_mv].clone(),
-                is_max: true,
+                is_max: ply.is_multiple_of(2),
                 alpha,
                 beta,
                 pruned: false,
             })),
             false => Option::None,
         };
-        let mut new_state = state.clone();
-        new_state.do_move(p_mv);
-        let (v2, t_move) = min_value(
-            new_state,
-            depth - 1,
-            max_depth,
-            alpha,
-            beta,
-            time_limit,
-            now,
-            h_s,
-            &mut inner_tree,
-        );
-        // should I update stuff
+        let undo = state.make_move(p_mv);
+        let (mut v2, mut t_move) = if i == 0 {
+            negamax(
+                state,
+                depth - 1,
+                max_depth,
+                -beta,
+                -alpha,
+                ctx,
+                &mut inner_tree,
+            )
+        } else {
+            negamax(
+                state,
+                depth - 1,
+                max_depth,
+                -alpha - 1,
+                -alpha,
+                ctx,
+                &mut inner_tree,
+            )
+        };
+        v2 = -v2;
+        if i > 0 && v2 > alpha && v2 < beta && !matches!(t_move, ABResult::TimeLimitExpired) {
+            ctx.stats.pvs_researches += 1;
+            #[cfg(feature = "tree_debug")]
+            {
+                inner_tree.as_mut().unwrap().clear();
+            }
+            let (v3, t3) = negamax(
+                state,
+                depth - 1,
+                max_depth,
+                -beta,
+                -alpha,
+                ctx,
+                &mut inner_tree,
+            );
+            (v2, t_move) = (-v3, t3);
+        }
+        state.unmake_move(undo);
+        // only the first move can still be on the last iteration's best line
+        ctx.follow_pv = false;
         if v2 > v {
             v = v2;
             mv = t_move.set(p_mv);
+            best_mv = Some(p_mv);
+            ctx.update_pv(ply, p_mv);
             if v > alpha {
                 alpha = v;
             }
@@ -296,107 +1002,476 @@ fn max_value(
             return (v, t_move);
         }
         // should I prune
-        if v >= beta {
+        if alpha >= beta {
+            ctx.record_cutoff(state, p_mv, ply, depth, i == 0);
             #[cfg(feature = "tree_debug")]
             {
-                for pruned_mv in p_mv..state.get_player_info().borrow().get_moves().len() {
+                for &pruned_mv in order[i + 1..].iter() {
                     tree.as_mut().unwrap().push(Tree::new(RTTree {
                         alpha,
                         beta,
                         h_val: 0,
-                        mv: state.get_player_info().borrow().get_moves()[pruned_mv].clone(),
-                        is_max: true,
+                        mv: state.get_player_info().get_moves()[pruned_mv].clone(),
+                        is_max: ply.is_multiple_of(2),
                         pruned: true,
                     }));
                 }
             }
-            return (v, mv);
+            break;
         }
     }
-    return (v, mv);
+    let bound = if v <= alpha_orig {
+        Bound::Upper
+    } else if v >= beta {
+        Bound::Lower
+    } else {
+        Bound::Exact
+    };
+    // without some of its moves the root's score isn't the position's
+    if ply > 0 || ctx.root_excluded.is_empty() {
+        ctx.tt.store(
+            state.get_hash(),
+            depth,
+            score_to_tt(v, ply as u32),
+            bound,
+            best_mv,
+        );
+    }
+    (v, mv)
 }
 
-fn min_value(
-    state: Board,
-    depth: u32,
-    max_depth: &u32,
-    alpha: i32,
-    mut beta: i32,
-    time_limit: u128,
-    now: &SystemTime,
-    h_s: &Heuristic,
-    tree: &mut Option<Tree<RTTree>>,
-) -> (i32, ABResult) {
-    match is_terminal(&state, depth, max_depth, time_limit, now, false, h_s) {
-        Result::Ok(r) => return r,
-        Result::Err(_) => {}
-    };
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
 
-    let mut v = MAX;
-    let mut mv = ABResult::Inital;
-    for p_mv in 0..state.get_player_info().get_moves().len() {
-        let mut inner_tree: Option<Tree<RTTree>> = match cfg!(feature = "tree_debug") {
-            true => Option::Some(Tree::new(RTTree {
-                h_val: 0,
-                mv: state.get_player_info().get_moves()[p_mv].clone(),
-                is_max: false,
-                alpha,
-                beta,
-                pruned: false,
-            })),
-            false => Option::None,
+    // what the search threads share
+    struct Shared {
+        limits: SearchLimits,
+        tt: TranspositionTable,
+        stop: AtomicBool,
+    }
+
+    impl Shared {
+        fn new(tt_size: usize) -> Self {
+            Self {
+                limits: SearchLimits::default(),
+                tt: TranspositionTable::new(tt_size),
+                stop: AtomicBool::new(false),
+            }
+        }
+    }
+
+    fn test_ctx<'a>(h_s: &'a Heuristic, shared: &'a Shared) -> SearchContext<'a> {
+        SearchContext::new(&shared.limits, h_s, &shared.tt, &shared.stop)
+    }
+
+    // iterative deepening up to `depth` the way predict_move does it, without the time limit
+    fn deepen(b: &Board, depth: u32, ctx: &mut SearchContext) -> (usize, i32) {
+        let mut state = b.clone();
+        let mut best = None;
+        for d in 1..=depth {
+            ctx.start_iteration(d);
+            let prev_score = best.map(|(_, score)| score);
+            match aspiration_search(&mut state, d, prev_score, ctx, &mut None) {
+                (score, ABResult::DepthReached(Some(mv))) => {
+                    best = Some((mv, score));
+                    ctx.prev_pv = ctx.pv_table[0].clone();
+                }
+                (score, ABResult::Finished(Some(mv))) => return (mv, score),
+                _ => panic!("search didn't return a move"),
+            }
+        }
+        best.expect("searched at least one depth")
+    }
+
+    fn search_ctx<'a>(
+        b: &Board,
+        depth: u32,
+        h_s: &'a Heuristic,
+        shared: &'a Shared,
+    ) -> (usize, SearchContext<'a>) {
+        let mut ctx = test_ctx(h_s, shared);
+        let (mv, _) = deepen(b, depth, &mut ctx);
+        (mv, ctx)
+    }
+
+    // the whole tree without any pruning, scored by the same leaves as the real search
+    fn minimax(state: &mut Board, depth: u32, max_depth: u32, ctx: &mut SearchContext) -> i32 {
+        if let Result::Ok((v, _)) = is_terminal(state, depth, &max_depth, MIN, MAX, ctx) {
+            return v;
+        }
+        (0..state.get_player_info().get_moves().len())
+            .map(|mv| {
+                let undo = state.make_move(mv);
+                let v = -minimax(state, depth - 1, max_depth, ctx);
+                state.unmake_move(undo);
+                v
+            })
+            .max()
+            .expect("a position that isn't over has moves")
+    }
+
+    fn search(b: &Board, depth: u32, h_s: &Heuristic) -> (usize, SearchStats) {
+        let shared = Shared::new(1 << 16);
+        let (mv, ctx) = search_ctx(b, depth, h_s, &shared);
+        (mv, ctx.stats)
+    }
+
+    #[test]
+    fn test_quiescence_sees_pending_capture() {
+        // 10-15 lets the man on 19 take it, at depth 1 only quiescence can see that
+        let b = Board::from_fen("B:W19,32:B1,10").unwrap();
+        let h_s = Heuristic::default_new();
+        let (mv, stats) = search(&b, 1, &h_s);
+        assert_ne!(b.get_player_info().get_moves()[mv].notation(), "10-15");
+        assert!(stats.quiescence_searches > 0);
+        assert!(stats.max_quiescence_ply >= 1);
+        assert_eq!(stats.quiescence_cutoffs, 0);
+    }
+
+    #[test]
+    fn test_quiescence_window() {
+        let h_s = Heuristic::default_new();
+        let shared = Shared::new(0);
+        let mut b = Board::default();
+        for mv in ["11-15", "23-19", "8-11", "22-17", "9-13", "17-14", "10x17"] {
+            let m = b.parse_move(mv).unwrap();
+            b.do_move(m);
+        }
+        // red has to take back on 17
+        assert!(b.get_player_info().get_can_jump());
+        let mut quiesce_with = |alpha, beta| {
+            let mut ctx = test_ctx(&h_s, &shared);
+            let mut nodes_left = QUIESCENCE_NODE_LIMIT;
+            let v = quiesce(&mut b, 0, 0, alpha, beta, &mut nodes_left, &mut ctx);
+            (v, ctx.stats.quiescence_nodes)
         };
-        let mut new_state = state.clone();
-        new_state.do_move(p_mv);
-        let (v2, t_move) = max_value(
-            new_state,
-            depth - 1,
-            max_depth,
-            alpha,
-            beta,
-            time_limit,
-            now,
-            h_s,
-            &mut inner_tree,
-        );
+        let (exact, all_nodes) = quiesce_with(MIN, MAX);
+        assert_eq!(quiesce_with(exact - 1, exact + 1).0, exact);
+        // outside of the window the score is only a bound, found with fewer nodes
+        let (v, nodes) = quiesce_with(exact + 10, exact + 20);
+        assert!(v <= exact + 10);
+        assert!(nodes <= all_nodes);
+        let (v, nodes) = quiesce_with(exact - 20, exact - 10);
+        assert!(v >= exact - 10);
+        assert!(nodes <= all_nodes);
+    }
 
-        if v2 < v {
-            v = v2;
-            mv = t_move.set(p_mv);
-            if v < beta {
-                beta = v
+    #[test]
+    fn test_history_halves() {
+        let h_s = Heuristic::default_new();
+        let shared = Shared::new(0);
+        let mut ctx = test_ctx(&h_s, &shared);
+        ctx.start_iteration(4);
+        let b = Board::default();
+        let (start, end) = move_key(&b.get_player_info().get_moves()[0]);
+        ctx.history[start][end] = HISTORY_LIMIT;
+        ctx.history[0][0] = 10;
+        ctx.record_cutoff(&b, 0, 0, 4, true);
+        assert_eq!(ctx.history[start][end], (HISTORY_LIMIT + 16) / 2);
+        assert_eq!(ctx.history[0][0], 5);
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        let h_s = Heuristic::default_new();
+        let b = Board::default();
+        let shared = Shared::new(1 << 16);
+        let (mv, ctx) = search_ctx(&b, 6, &h_s, &shared);
+        let pv = &ctx.prev_pv;
+        assert_eq!(pv.first(), Some(&mv));
+        assert!(pv.len() > 1);
+        // every move of the line has to be legal where it is played
+        let mut state = b.clone();
+        for &m in pv.iter() {
+            assert!(state.do_move(m));
+        }
+        assert!(ctx.stats.cutoffs > 0);
+        assert!(ctx.stats.first_move_cutoffs <= ctx.stats.cutoffs);
+        assert!(ctx.killers.iter().any(|k| k[0].is_some()));
+    }
+
+    #[test]
+    fn test_matches_minimax() {
+        let h_s = Heuristic::default_new();
+        let mut positions = vec![Board::default()];
+        let mut b = Board::default();
+        for mv in [
+            "11-15", "23-19", "8-11", "22-17", "9-13", "17-14", "10x17", "21x14",
+        ] {
+            let m = b.parse_move(mv).unwrap();
+            b.do_move(m);
+            positions.push(b.clone());
+        }
+        positions.push(Board::from_fen("B:W19,32:B1,10").unwrap());
+        positions.push(Board::from_fen("W:WK14,22,27:B6,K11,12").unwrap());
+
+        for b in positions.iter() {
+            for depth in 1..=4 {
+                // without the table every score is exact and has to match the full tree
+                let shared = Shared::new(0);
+                let mut ctx = test_ctx(&h_s, &shared);
+                let (mv, score) = deepen(b, depth, &mut ctx);
+
+                let mut reference = test_ctx(&h_s, &shared);
+                reference.start_iteration(depth);
+                let mut state = b.clone();
+                let scores: Vec<i32> = (0..b.get_player_info().get_moves().len())
+                    .map(|m| {
+                        let undo = state.make_move(m);
+                        let v = -minimax(&mut state, depth - 1, depth, &mut reference);
+                        state.unmake_move(undo);
+                        v
+                    })
+                    .collect();
+                let best = *scores.iter().max().unwrap();
+                assert_eq!(score, best, "{} at depth {}", b.to_fen(), depth);
+                assert_eq!(scores[mv], best, "{} at depth {}", b.to_fen(), depth);
             }
         }
+    }
 
-        #[cfg(feature = "tree_debug")]
-        {
-            inner_tree.as_mut().unwrap().val.h_val = v2;
-            inner_tree.as_mut().unwrap().val.alpha = alpha;
-            inner_tree.as_mut().unwrap().val.beta = beta;
-            tree.as_mut().unwrap().push(inner_tree.unwrap());
+    #[test]
+    fn test_threaded_search() {
+        let b = Board::default();
+        let options = SearchOptions {
+            threads: 4,
+            ..Default::default()
+        };
+        let mv =
+            predict_move_threaded(b.clone(), SearchLimits::from_secs(1), None, &options).best_move;
+        assert!(mv < b.get_player_info().get_moves().len());
+    }
+
+    #[test]
+    fn test_search_limits() {
+        let b = Board::default();
+        let moves = b.get_player_info().get_moves().len();
+        let start = Instant::now();
+        assert!(predict_move(b.clone(), SearchLimits::from_depth(4), None).best_move < moves);
+        assert!(predict_move(b.clone(), SearchLimits::from_nodes(5000), None).best_move < moves);
+        let time = Duration::from_millis(300);
+        let mv = predict_move(
+            b.clone(),
+            SearchLimits {
+                time: Some(time),
+                ..Default::default()
+            },
+            None,
+        )
+        .best_move;
+        assert!(mv < moves);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let h_s = Heuristic::default_new();
+        let mut shared = Shared::new(1 << 10);
+        shared.limits = SearchLimits::from_nodes(5000);
+        let mut ctx = test_ctx(&h_s, &shared);
+        let mut state = b.clone();
+        for d in 1..30 {
+            ctx.start_iteration(d);
+            let (_, res) = aspiration_search(&mut state, d, None, &mut ctx, &mut None);
+            if let ABResult::TimeLimitExpired = res {
+                break;
+            }
         }
+        // the node that hits the limit gets to finish its leaves before the search notices
+        assert!(ctx.stats.nodes + ctx.stats.quiescence_nodes < 5100);
+    }
 
-        // time limit expired get out
-        if let ABResult::TimeLimitExpired = t_move {
-            return (v, t_move);
+    #[test]
+    fn test_search_result() {
+        let b = Board::default();
+        let result = predict_move(b.clone(), SearchLimits::from_depth(5), None);
+        assert_eq!(result.depth, 5);
+        assert_eq!(result.pv.first(), Some(&result.best_move));
+        assert_eq!(result.pv_notation(&b).len(), result.pv.len());
+        assert!(result.score.is_some() && result.mate_in().is_none());
+        assert!(result.nodes > 0);
+
+        // black wins in three plies by trapping the man on 28 and taking it
+        let b = Board::from_fen("B:W28:BK18,K19").unwrap();
+        let result = predict_move(b.clone(), SearchLimits::from_depth(12), None);
+        assert_eq!(result.mate_in(), Some(3));
+        let pv = result.pv_notation(&b);
+        assert_eq!(pv.len(), 3);
+        assert!(pv[2].ends_with("x28"));
+    }
+
+    #[test]
+    fn test_drawn_root() {
+        // the kings go back and forth until the start position is on the board a third time
+        let mut b = Board::from_fen("B:WK32:BK1,5").unwrap();
+        for _ in 0..2 {
+            for mv in ["1-6", "32-27", "6-1", "27-32"] {
+                b.do_move(b.parse_move(mv).unwrap());
+            }
         }
+        assert!(matches!(b.is_game_over(), Some(GameOutcome::Draw(_))));
+        let result = predict_move(b.clone(), SearchLimits::from_depth(3), None);
+        assert!(result.best_move < b.get_player_info().get_moves().len());
+        assert_eq!(result.depth, 3);
+    }
 
-        if v <= alpha {
-            #[cfg(feature = "tree_debug")]
-            {
-                for pruned_mv in p_mv..state.get_player_info().borrow().get_moves().len() {
-                    tree.as_mut().unwrap().push(Tree::new(RTTree {
-                        alpha,
-                        beta,
-                        h_val: 0,
-                        mv: state.get_player_info().borrow().get_moves()[pruned_mv].clone(),
-                        is_max: false,
-                        pruned: true,
-                    }));
-                }
+    #[test]
+    fn test_lost_position() {
+        // the man on 26 is blocked by the men in front of it
+        let b = Board::from_fen("B:W30,31:B26").unwrap();
+        assert!(b.get_player_info().get_moves().is_empty());
+        assert!(predict_move(b.clone(), SearchLimits::from_depth(4), None).resign);
+        let options = SearchOptions {
+            threads: 2,
+            ..Default::default()
+        };
+        assert!(predict_move_threaded(b, SearchLimits::from_depth(4), None, &options).resign);
+    }
+
+    #[test]
+    fn test_no_limits() {
+        // nothing can be proven with two kings each, without a depth cap this never ends
+        let b = Board::from_fen("B:WK29,K32:BK1,K4").unwrap();
+        let lines = analyze(b.clone(), SearchLimits::default(), None, 2, None);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.depth == DEFAULT_DEPTH));
+        let result = predict_move(b, SearchLimits::default(), None);
+        assert_eq!(result.depth, DEFAULT_DEPTH);
+    }
+
+    #[test]
+    fn test_analyze() {
+        let h_s = Heuristic::default_new();
+        let shared = Shared::new(0);
+        let mut ctx = test_ctx(&h_s, &shared);
+        for b in [
+            Board::default(),
+            Board::from_fen("W:WK14,22,27:B6,K11,12").unwrap(),
+        ] {
+            let lines = analyze(b.clone(), SearchLimits::from_depth(4), None, 3, None);
+            assert_eq!(lines.len(), 3);
+            let mut state = b.clone();
+            for line in lines.iter() {
+                let undo = state.make_move(line.best_move);
+                let score = -minimax(&mut state, 3, 4, &mut ctx);
+                state.unmake_move(undo);
+                assert_eq!(line.score, Some(score));
+                assert_eq!(line.pv[0], line.best_move);
+                assert_eq!(line.depth, 4);
             }
-            return (v, mv);
+            // the three best, each move once
+            let mut scores: Vec<i32> = (0..state.get_player_info().get_moves().len())
+                .map(|mv| {
+                    let undo = state.make_move(mv);
+                    let score = -minimax(&mut state, 3, 4, &mut ctx);
+                    state.unmake_move(undo);
+                    score
+                })
+                .collect();
+            scores.sort_by_key(|&s| std::cmp::Reverse(s));
+            let found: Vec<i32> = lines.iter().map(|l| l.score.unwrap()).collect();
+            assert_eq!(found, scores[..3]);
+            assert!(lines[0].best_move != lines[1].best_move);
+            assert!(lines[1].best_move != lines[2].best_move);
+            assert!(lines[0].best_move != lines[2].best_move);
+        }
+
+        // asking for more lines than there are moves gives every move
+        let b = Board::from_fen("B:W28:BK18,K19").unwrap();
+        let moves = b.get_player_info().get_moves().len();
+        assert_eq!(
+            analyze(b, SearchLimits::from_depth(3), None, 20, None).len(),
+            moves
+        );
+    }
+
+    #[test]
+    fn test_info() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let limits = SearchLimits {
+            depth: Some(4),
+            info: Some(tx),
+            ..Default::default()
+        };
+        let b = Board::default();
+        let result = predict_move(b, limits, None);
+        let infos: Vec<SearchResult> = rx.iter().collect();
+        assert_eq!(
+            infos.iter().map(|r| r.depth).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(infos[3].pv, result.pv);
+        assert!(infos.windows(2).all(|w| w[0].nodes <= w[1].nodes));
+    }
+
+    #[test]
+    fn test_stop_flag() {
+        // without any other limit only the flag can end the search
+        let stop = Arc::new(AtomicBool::new(false));
+        let limits = SearchLimits {
+            stop: Some(Arc::clone(&stop)),
+            ..Default::default()
+        };
+        let b = Board::default();
+        let search = {
+            let b = b.clone();
+            thread::spawn(move || predict_move(b, limits, None).best_move)
+        };
+        thread::sleep(Duration::from_millis(200));
+        stop.store(true, Ordering::Relaxed);
+        let mv = search.join().unwrap();
+        assert!(mv < b.get_player_info().get_moves().len());
+    }
+
+    #[test]
+    fn test_tablebase() {
+        let tb = tablebase::test::three_pieces();
+        // one ply is far too shallow to see the win, the tables play it anyway
+        let b = Board::from_fen("B:W28:BK18,K19").unwrap();
+        let options = SearchOptions {
+            tablebase: Some(tb),
+            ..Default::default()
+        };
+        let result = predict_move_threaded(b.clone(), SearchLimits::from_depth(1), None, &options);
+        assert_eq!(result.mate_in(), Some(3));
+        assert_eq!(Some(result.best_move), tb.best_move(&b).map(|(mv, _)| mv));
+        assert_eq!(result.pv.len(), 3);
+
+        // below the root every line is scored by the tables
+        let b = Board::from_fen("W:W27,K32:BK10").unwrap();
+        let moves = b.get_player_info().get_moves().len();
+        for line in analyze(
+            b.clone(),
+            SearchLimits::from_depth(1),
+            None,
+            moves,
+            Some(tb),
+        ) {
+            let mut next = b.clone();
+            next.do_move(line.best_move);
+            let value = tb.probe(&next).unwrap();
+            assert_eq!(line.score, Some(-tablebase_score(value, 1)));
         }
     }
-    return (v, mv);
+
+    #[test]
+    fn test_reproducible() {
+        let mut b = Board::default();
+        b.do_move(2);
+        let search = |options: &SearchOptions| {
+            let result =
+                predict_move_threaded(b.clone(), SearchLimits::from_depth(5), None, options);
+            (result.best_move, result.score, result.pv, result.nodes)
+        };
+        // without noise, or with the same seed for it, a search comes out the same every time
+        let plain = SearchOptions {
+            noise: false,
+            ..Default::default()
+        };
+        assert_eq!(search(&plain), search(&plain));
+        let seeded = SearchOptions {
+            seed: Some(42),
+            ..Default::default()
+        };
+        assert_eq!(search(&seeded), search(&seeded));
+    }
 }
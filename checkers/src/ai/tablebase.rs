@@ -0,0 +1,570 @@
+// Endgame tables built by retrograde analysis. Every position with up to `max_pieces` pieces is
+// stored as a win, loss or draw for the player to move, wins and losses with the number of plies
+// until the game ends when both sides play perfectly. The draw rules are ignored, like in perft.
+//
+// Positions are always stored with the player to move as black, red to move is turned around
+// first. Each material balance (men and kings of both sides) has its own table with one byte per
+// position, indexed by the squares of each group of pieces.
+
+use crate::board::{Board, Player};
+use anyhow::{bail, ensure, Context, Result};
+use std::collections::HashMap;
+use std::fs::{read, write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TbValue {
+    // plies until the player to move wins
+    Win(u32),
+    // plies until the player to move loses, 0 when they have no move
+    Loss(u32),
+    Draw,
+}
+
+impl TbValue {
+    // the value of the position before the move that led here
+    fn parent(self) -> Self {
+        match self {
+            Self::Win(plies) => Self::Loss(plies + 1),
+            Self::Loss(plies) => Self::Win(plies + 1),
+            Self::Draw => Self::Draw,
+        }
+    }
+
+    // bigger is better for the player to move: quick wins, then draws, then slow losses
+    fn rank(self) -> i64 {
+        match self {
+            Self::Win(plies) => i64::MAX - plies as i64,
+            Self::Draw => 0,
+            Self::Loss(plies) => i64::MIN + plies as i64,
+        }
+    }
+}
+
+// men and kings of the player to move, then men and kings of the other player
+type Material = [u8; 4];
+
+const MAGIC: &[u8; 4] = b"CKTB";
+const VERSION: u8 = 1;
+// beyond this the tables no longer fit in memory
+const MAX_PIECES: u32 = 6;
+
+// one byte per position: 0 is a draw, 1..=127 a win in 2v - 1 plies, 128..=255 a loss in
+// 2(v - 128) plies. Wins always take an odd number of plies and losses an even one.
+const LOSS_BASE: u8 = 128;
+
+fn encode(value: TbValue) -> Result<u8> {
+    match value {
+        TbValue::Draw => Ok(0),
+        TbValue::Win(plies) if plies <= 253 => Ok(plies.div_ceil(2) as u8),
+        TbValue::Loss(plies) if plies <= 254 => Ok(LOSS_BASE + (plies / 2) as u8),
+        _ => bail!("{:?} is too long to be stored", value),
+    }
+}
+
+fn decode(byte: u8) -> TbValue {
+    match byte {
+        0 => TbValue::Draw,
+        1..LOSS_BASE => TbValue::Win(2 * byte as u32 - 1),
+        _ => TbValue::Loss(2 * (byte - LOSS_BASE) as u32),
+    }
+}
+
+// a man of the player to move is never on the last row and one of the other player never on the
+// first, so men are indexed over the 28 squares they can stand on
+const ROW_0: u32 = 0x0000_000F;
+const ROW_7: u32 = 0xF000_0000;
+// first square of each group and how many squares it can use
+const GROUPS: [(u32, u32); 4] = [(0, 28), (0, 32), (4, 28), (0, 32)];
+
+fn binomial(n: u32, k: u32) -> u64 {
+    if k > n {
+        return 0;
+    }
+    (0..k as u64).fold(1, |acc, i| acc * (n as u64 - i) / (i + 1))
+}
+
+// the position of a set of squares among all sets of the same size
+fn rank(bb: u32, first: u32) -> u64 {
+    let mut rank = 0;
+    let mut rest = bb;
+    let mut i = 1;
+    while rest != 0 {
+        let sq = rest.trailing_zeros();
+        rank += binomial(sq - first, i);
+        rest &= rest - 1;
+        i += 1;
+    }
+    rank
+}
+
+fn unrank(mut rank: u64, count: u32, first: u32, squares: u32) -> u32 {
+    let mut bb = 0;
+    let mut top = squares;
+    for i in (1..=count).rev() {
+        top -= 1;
+        while binomial(top, i) > rank {
+            top -= 1;
+        }
+        rank -= binomial(top, i);
+        bb |= 1 << (top + first);
+    }
+    bb
+}
+
+fn table_size(material: Material) -> u64 {
+    material
+        .iter()
+        .zip(GROUPS)
+        .map(|(&count, (_, squares))| binomial(squares, count as u32))
+        .product()
+}
+
+// the player to move's pieces, the other player's pieces and the kings, with the player to move
+// as black
+fn normalize(b: &Board) -> (u32, u32, u32) {
+    let (black, red, kings) = b.get_bitboards();
+    match b.get_current_player() {
+        Player::Black => (black, red, kings),
+        // turning the board around reverses the square numbers
+        Player::Red => (
+            red.reverse_bits(),
+            black.reverse_bits(),
+            kings.reverse_bits(),
+        ),
+    }
+}
+
+fn material_of(us: u32, them: u32, kings: u32) -> Material {
+    [
+        (us & !kings).count_ones() as u8,
+        (us & kings).count_ones() as u8,
+        (them & !kings).count_ones() as u8,
+        (them & kings).count_ones() as u8,
+    ]
+}
+
+fn index_of(us: u32, them: u32, kings: u32) -> u64 {
+    let groups = [us & !kings, us & kings, them & !kings, them & kings];
+    let material = material_of(us, them, kings);
+    groups
+        .iter()
+        .zip(GROUPS)
+        .zip(material)
+        .fold(0, |index, ((&bb, (first, squares)), count)| {
+            index * binomial(squares, count as u32) + rank(bb, first)
+        })
+}
+
+// None for indexes that put two pieces on the same square
+fn position_at(material: Material, mut index: u64) -> Option<(u32, u32, u32)> {
+    let mut groups = [0; 4];
+    for g in (0..4).rev() {
+        let (first, squares) = GROUPS[g];
+        let size = binomial(squares, material[g] as u32);
+        groups[g] = unrank(index % size, material[g] as u32, first, squares);
+        index /= size;
+    }
+    let [us_men, us_kings, them_men, them_kings] = groups;
+    let occupied = us_men.count_ones()
+        + us_kings.count_ones()
+        + them_men.count_ones()
+        + them_kings.count_ones();
+    if (us_men | us_kings | them_men | them_kings).count_ones() != occupied {
+        return None;
+    }
+    debug_assert_eq!((us_men & ROW_7) | (them_men & ROW_0), 0);
+    Some((
+        us_men | us_kings,
+        them_men | them_kings,
+        us_kings | them_kings,
+    ))
+}
+
+// every material balance with both sides on the board and at most `max_pieces` pieces, in the order
+// they have to be solved in: captures lead to fewer pieces and crowning to fewer men
+fn materials(max_pieces: u32) -> Vec<Material> {
+    let mut all = Vec::new();
+    let max = max_pieces as u8;
+    for m1 in 0..=max {
+        for k1 in 0..=max - m1 {
+            for m2 in 0..=max - m1 - k1 {
+                for k2 in 0..=max - m1 - k1 - m2 {
+                    if m1 + k1 > 0 && m2 + k2 > 0 {
+                        all.push([m1, k1, m2, k2]);
+                    }
+                }
+            }
+        }
+    }
+    all.sort_by_key(|m| (m.iter().sum::<u8>(), m[0] + m[2]));
+    all
+}
+
+fn swap_sides(material: Material) -> Material {
+    [material[2], material[3], material[0], material[1]]
+}
+
+// What a move leads to: the index of a position of the tables being solved, or with KNOWN set
+// the stored byte of a position whose value is already known. Kept to four bytes as there are a
+// lot of them.
+type Successor = u32;
+const KNOWN: u32 = 1 << 31;
+
+pub struct Tablebase {
+    max_pieces: u32,
+    tables: HashMap<Material, Vec<u8>>,
+}
+
+impl std::fmt::Debug for Tablebase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tablebase")
+            .field("max_pieces", &self.max_pieces)
+            .field("tables", &self.tables.len())
+            .finish()
+    }
+}
+
+impl Tablebase {
+    pub fn generate(max_pieces: u32) -> Result<Self> {
+        ensure!(
+            (2..=MAX_PIECES).contains(&max_pieces),
+            "Tables can be made for 2 to {} pieces, not {}",
+            MAX_PIECES,
+            max_pieces
+        );
+        let mut tb = Self {
+            max_pieces,
+            tables: HashMap::new(),
+        };
+        for material in materials(max_pieces) {
+            if tb.tables.contains_key(&material) {
+                continue;
+            }
+            // a move without a capture or crowning only swaps whose turn it is, so a material
+            // balance is solved together with its mirror image
+            let group = match swap_sides(material) {
+                other if other == material => vec![material],
+                other => vec![material, other],
+            };
+            let tables = tb.solve(&group)?;
+            tb.tables.extend(group.into_iter().zip(tables));
+        }
+        Ok(tb)
+    }
+
+    pub fn get_max_pieces(&self) -> u32 {
+        self.max_pieces
+    }
+
+    // the stored value of every table, for showing what was generated
+    pub fn summary(&self) -> Vec<(String, usize, usize, usize)> {
+        let mut materials: Vec<&Material> = self.tables.keys().collect();
+        materials.sort_by_key(|m| (m.iter().sum::<u8>(), m[0] + m[2], **m));
+        materials
+            .into_iter()
+            .map(|&material| {
+                let table = &self.tables[&material];
+                let mut counts = (0, 0, 0);
+                for (index, &byte) in table.iter().enumerate() {
+                    if position_at(material, index as u64).is_none() {
+                        continue;
+                    }
+                    match decode(byte) {
+                        TbValue::Win(_) => counts.0 += 1,
+                        TbValue::Loss(_) => counts.1 += 1,
+                        TbValue::Draw => counts.2 += 1,
+                    }
+                }
+                let [m1, k1, m2, k2] = material;
+                let name = format!("{}m{}k vs {}m{}k", m1, k1, m2, k2);
+                (name, counts.0, counts.1, counts.2)
+            })
+            .collect()
+    }
+
+    fn solve(&self, group: &[Material]) -> Result<Vec<Vec<u8>>> {
+        let sizes: Vec<usize> = group.iter().map(|&m| table_size(m) as usize).collect();
+        let offsets: Vec<usize> = sizes
+            .iter()
+            .scan(0, |acc, size| {
+                let offset = *acc;
+                *acc += size;
+                Some(offset)
+            })
+            .collect();
+        let total: usize = sizes.iter().sum();
+
+        // the successors of position p are successors[starts[p]..starts[p + 1]], positions that
+        // can't exist have none and are left out
+        let mut starts = Vec::with_capacity(total + 1);
+        let mut successors = Vec::new();
+        let mut exists = vec![false; total];
+        for (&material, &offset) in group.iter().zip(offsets.iter()) {
+            for index in 0..table_size(material) {
+                starts.push(successors.len());
+                let (us, them, kings) = match position_at(material, index) {
+                    Some(position) => position,
+                    None => continue,
+                };
+                exists[offset + index as usize] = true;
+                let mut b = Board::from_bitboards(us, them, kings, Player::Black)?;
+                for mv in 0..b.get_player_info().get_moves().len() {
+                    let undo = b.make_move(mv);
+                    successors.push(self.successor(&b, group, &offsets)?);
+                    b.unmake_move(undo);
+                }
+            }
+        }
+        starts.push(successors.len());
+
+        let mut values: Vec<Option<TbValue>> = vec![None; total];
+        let mut longest_known = 0;
+        for p in 0..total {
+            if exists[p] && starts[p] == starts[p + 1] {
+                values[p] = Some(TbValue::Loss(0));
+            }
+        }
+        for s in successors.iter().filter(|&s| s & KNOWN != 0) {
+            if let TbValue::Win(plies) | TbValue::Loss(plies) = decode(*s as u8) {
+                longest_known = longest_known.max(plies);
+            }
+        }
+
+        // In round d every position that is won or lost in d plies gets its value: a win needs a
+        // move to a position lost in d - 1, a loss needs every move to go to a win and the slowest
+        // of them to take d - 1 plies. Once a round finds nothing and no known value is long
+        // enough to matter any more, the rest are draws.
+        let mut d = 1;
+        loop {
+            let mut found = Vec::new();
+            for p in 0..total {
+                if !exists[p] || values[p].is_some() {
+                    continue;
+                }
+                let mut all_wins = true;
+                let mut slowest_win = 0;
+                let mut wins = false;
+                for s in successors[starts[p]..starts[p + 1]].iter() {
+                    let value = match s & KNOWN {
+                        0 => values[*s as usize],
+                        _ => Some(decode(*s as u8)),
+                    };
+                    match value {
+                        Some(TbValue::Loss(plies)) if plies + 1 == d => {
+                            wins = true;
+                            break;
+                        }
+                        Some(TbValue::Win(plies)) => slowest_win = slowest_win.max(plies),
+                        _ => all_wins = false,
+                    }
+                }
+                if wins {
+                    found.push((p, TbValue::Win(d)));
+                } else if all_wins && slowest_win + 1 == d {
+                    found.push((p, TbValue::Loss(d)));
+                }
+            }
+            if found.is_empty() && d > longest_known + 1 {
+                break;
+            }
+            for (p, value) in found {
+                values[p] = Some(value);
+            }
+            d += 1;
+        }
+
+        let bytes = values
+            .into_iter()
+            .map(|value| encode(value.unwrap_or(TbValue::Draw)))
+            .collect::<Result<Vec<u8>>>()?;
+        Ok(offsets
+            .iter()
+            .zip(sizes.iter())
+            .map(|(&offset, &size)| bytes[offset..offset + size].to_vec())
+            .collect())
+    }
+
+    fn successor(&self, b: &Board, group: &[Material], offsets: &[usize]) -> Result<Successor> {
+        if b.get_player_info().get_moves().is_empty() {
+            return Ok(KNOWN | encode(TbValue::Loss(0))? as u32);
+        }
+        let (us, them, kings) = normalize(b);
+        let material = material_of(us, them, kings);
+        let index = index_of(us, them, kings) as usize;
+        if let Some(g) = group.iter().position(|&m| m == material) {
+            let local = offsets[g] + index;
+            ensure!(
+                local < KNOWN as usize,
+                "Too many positions to solve at once"
+            );
+            return Ok(local as u32);
+        }
+        match self.tables.get(&material) {
+            Some(table) => Ok(KNOWN | table[index] as u32),
+            None => bail!("The table for {:?} has to be solved first", material),
+        }
+    }
+
+    // None if the position has more pieces than the tables hold
+    pub fn probe(&self, b: &Board) -> Option<TbValue> {
+        if b.get_player_info().get_moves().is_empty() {
+            return Some(TbValue::Loss(0));
+        }
+        let (us, them, kings) = normalize(b);
+        if (us | them).count_ones() > self.max_pieces {
+            return None;
+        }
+        let table = self.tables.get(&material_of(us, them, kings))?;
+        Some(decode(table[index_of(us, them, kings) as usize]))
+    }
+
+    // the move that wins fastest, holds the draw or loses slowest, with the position's value
+    pub fn best_move(&self, b: &Board) -> Option<(usize, TbValue)> {
+        let mut b = b.clone();
+        let mut best: Option<(usize, TbValue)> = None;
+        for mv in 0..b.get_player_info().get_moves().len() {
+            let undo = b.make_move(mv);
+            let value = self.probe(&b).map(TbValue::parent);
+            b.unmake_move(undo);
+            let value = value?;
+            if best.is_none_or(|(_, v)| value.rank() > v.rank()) {
+                best = Some((mv, value));
+            }
+        }
+        best
+    }
+
+    // MAGIC, VERSION, max pieces, number of tables, then every table as its material, its
+    // length as a little endian u32 and one byte per position
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.max_pieces as u8);
+        bytes.extend_from_slice(&(self.tables.len() as u32).to_le_bytes());
+        let mut materials: Vec<&Material> = self.tables.keys().collect();
+        materials.sort();
+        for material in materials {
+            let table = &self.tables[material];
+            bytes.extend_from_slice(material);
+            bytes.extend_from_slice(&(table.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(table);
+        }
+        write(path, bytes).with_context(|| format!("Could not write {}", path))
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let bytes = read(path).with_context(|| format!("Could not read {}", path))?;
+        Self::from_bytes(&bytes).with_context(|| format!("Invalid tablebase file {}", path))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut rest = bytes;
+        let mut take = |n: usize| -> Result<&[u8]> {
+            ensure!(rest.len() >= n, "the file ends early");
+            let (taken, left) = rest.split_at(n);
+            rest = left;
+            Ok(taken)
+        };
+        ensure!(take(4)? == MAGIC, "not a tablebase file");
+        ensure!(take(1)?[0] == VERSION, "unknown version");
+        let max_pieces = take(1)?[0] as u32;
+        let count = u32::from_le_bytes(take(4)?.try_into()?);
+        let mut tables = HashMap::new();
+        for _ in 0..count {
+            let material: Material = take(4)?.try_into()?;
+            let len = u32::from_le_bytes(take(4)?.try_into()?) as usize;
+            ensure!(
+                len as u64 == table_size(material),
+                "the table for {:?} has the wrong size",
+                material
+            );
+            tables.insert(material, take(len)?.to_vec());
+        }
+        Ok(Self { max_pieces, tables })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::sync::OnceLock;
+
+    // generating is slow without optimizations, so the tests share one set of tables
+    pub(crate) fn three_pieces() -> &'static Tablebase {
+        static TB: OnceLock<Tablebase> = OnceLock::new();
+        TB.get_or_init(|| Tablebase::generate(3).unwrap())
+    }
+
+    #[test]
+    fn test_index() {
+        for material in [[1, 0, 0, 1], [2, 1, 1, 0], [0, 2, 1, 1]] {
+            let mut seen = 0;
+            for index in 0..table_size(material) {
+                if let Some((us, them, kings)) = position_at(material, index) {
+                    assert_eq!(material_of(us, them, kings), material);
+                    assert_eq!(index_of(us, them, kings), index);
+                    seen += 1;
+                }
+            }
+            assert!(seen > 0);
+        }
+        for v in [
+            TbValue::Draw,
+            TbValue::Win(1),
+            TbValue::Win(253),
+            TbValue::Loss(0),
+            TbValue::Loss(254),
+        ] {
+            assert_eq!(decode(encode(v).unwrap()), v);
+        }
+    }
+
+    #[test]
+    fn test_known_positions() {
+        let tb = three_pieces();
+        // black traps the man on 28 with its two kings
+        let b = Board::from_fen("B:W28:BK18,K19").unwrap();
+        assert_eq!(tb.probe(&b), Some(TbValue::Win(3)));
+        // the same position turned around with red to move
+        let b = Board::from_fen("W:WK14,K15:B5").unwrap();
+        assert_eq!(tb.probe(&b), Some(TbValue::Win(3)));
+        // one king against another can't be won
+        let b = Board::from_fen("B:WK32:BK1").unwrap();
+        assert_eq!(tb.probe(&b), Some(TbValue::Draw));
+        // too many pieces
+        assert_eq!(tb.probe(&Board::default()), None);
+    }
+
+    #[test]
+    fn test_consistent() {
+        // every value has to follow from the values after each move
+        let tb = three_pieces();
+        for material in [[0, 2, 0, 1], [1, 0, 0, 2], [1, 1, 1, 0]] {
+            for index in 0..table_size(material) {
+                let Some((us, them, kings)) = position_at(material, index) else {
+                    continue;
+                };
+                let b = Board::from_bitboards(us, them, kings, Player::Black).unwrap();
+                let value = tb.probe(&b).unwrap();
+                match tb.best_move(&b) {
+                    Some((_, best)) => assert_eq!(value, best, "{}", b.to_fen()),
+                    None => assert_eq!(value, TbValue::Loss(0)),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_file() {
+        let tb = three_pieces();
+        let path = std::env::temp_dir().join("checkers_test.tb");
+        let path = path.to_str().unwrap();
+        tb.save(path).unwrap();
+        let loaded = Tablebase::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.max_pieces, 3);
+        assert_eq!(loaded.tables, tb.tables);
+        assert!(Tablebase::from_bytes(b"CKTB").is_err());
+    }
+}
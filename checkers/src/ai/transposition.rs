@@ -0,0 +1,195 @@
+// Fixed size table of previously searched positions, indexed by the board's zobrist hash.
+// It is shared by every search thread without locking. Each slot holds the entry packed into one
+// word and the key xored with that word, a slot torn by two threads writing at once no longer
+// matches its key and is treated as empty.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const DEFAULT_TT_SIZE: usize = 1 << 18;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    // the stored score is the real value of the position
+    Exact,
+    // the search failed high, the real value is at least the stored score
+    Lower,
+    // the search failed low, the real value is at most the stored score
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub key: u64,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    pub best_move: Option<u8>,
+}
+
+// bits 0-31 score, 32-39 depth, 40-41 bound, 42 has a best move, 43-50 best move, 51 occupied
+const OCCUPIED: u64 = 1 << 51;
+const HAS_MOVE: u64 = 1 << 42;
+
+impl Entry {
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        let best_move = match self.best_move {
+            Some(mv) => HAS_MOVE | (mv as u64) << 43,
+            None => 0,
+        };
+        OCCUPIED | self.score as u32 as u64 | (self.depth as u64) << 32 | bound << 40 | best_move
+    }
+
+    fn unpack(key: u64, data: u64) -> Self {
+        Self {
+            key,
+            score: data as u32 as i32,
+            depth: (data >> 32) as u8,
+            bound: match (data >> 40) & 3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+            best_move: match data & HAS_MOVE {
+                0 => None,
+                _ => Some((data >> 43) as u8),
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    // key ^ data
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+    entries: Vec<Slot>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    // size is rounded up to the next power of two, a size of 0 gives a table that stores nothing
+    pub fn new(size: usize) -> Self {
+        let size = match size {
+            0 => 0,
+            _ => size.next_power_of_two(),
+        };
+        Self {
+            entries: (0..size).map(|_| Slot::default()).collect(),
+            mask: size.saturating_sub(1),
+        }
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let slot = self.entries.get(key as usize & self.mask)?;
+        let data = slot.data.load(Ordering::Relaxed);
+        let check = slot.check.load(Ordering::Relaxed);
+        if data & OCCUPIED == 0 || check ^ data != key {
+            return None;
+        }
+        Some(Entry::unpack(key, data))
+    }
+
+    // a newer position always takes the slot, but a shallower search never overwrites a deeper
+    // result for the same position
+    pub fn store(&self, key: u64, depth: u32, score: i32, bound: Bound, best_move: Option<usize>) {
+        let depth = depth.min(u8::MAX as u32) as u8;
+        let slot = match self.entries.get(key as usize & self.mask) {
+            Some(slot) => slot,
+            None => return,
+        };
+        if matches!(self.probe(key), Some(old) if old.depth > depth) {
+            return;
+        }
+        let data = Entry {
+            key,
+            score,
+            depth,
+            bound,
+            best_move: best_move.map(|m| m as u8),
+        }
+        .pack();
+        slot.data.store(data, Ordering::Relaxed);
+        slot.check.store(key ^ data, Ordering::Relaxed);
+    }
+
+    pub fn clear(&mut self) {
+        for slot in self.entries.iter_mut() {
+            *slot = Slot::default();
+        }
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_TT_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_deeper_entry_kept() {
+        let tt = TranspositionTable::new(16);
+        tt.store(42, 5, 100, Bound::Exact, Some(3));
+        tt.store(42, 2, -7, Bound::Lower, Some(1));
+        let entry = tt.probe(42).expect("entry was stored");
+        assert_eq!(entry.depth, 5);
+        assert_eq!(entry.best_move, Some(3));
+        // a different position in the same slot replaces it
+        tt.store(42 + 16, 1, 0, Bound::Upper, None);
+        assert!(tt.probe(42).is_none());
+        assert!(tt.probe(42 + 16).is_some());
+    }
+
+    #[test]
+    fn test_empty_table() {
+        let tt = TranspositionTable::new(0);
+        tt.store(42, 5, 100, Bound::Exact, Some(3));
+        assert!(tt.probe(42).is_none());
+    }
+
+    #[test]
+    fn test_pack() {
+        let tt = TranspositionTable::new(4);
+        tt.store(7, 300, -123_456, Bound::Upper, None);
+        let entry = tt.probe(7).unwrap();
+        assert_eq!(entry.score, -123_456);
+        assert_eq!(entry.depth, u8::MAX);
+        assert_eq!(entry.bound, Bound::Upper);
+        assert_eq!(entry.best_move, None);
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        // every thread writes entries whose contents follow from the key into the same few slots,
+        // whatever is read back has to belong to the key it was found under
+        let tt = TranspositionTable::new(8);
+        std::thread::scope(|scope| {
+            for t in 0..4u64 {
+                let tt = &tt;
+                scope.spawn(move || {
+                    for i in 0..20_000u64 {
+                        let key = (i * 4 + t) * 0x9E37_79B9;
+                        tt.store(key, 1, key as i32, Bound::Exact, Some((key % 200) as usize));
+                        for k in [key, key.wrapping_sub(3)] {
+                            if let Some(e) = tt.probe(k) {
+                                assert_eq!(e.score, k as i32);
+                                assert_eq!(e.best_move, Some((k % 200) as u8));
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,212 @@
+// Texel tuning: fits the heuristic weights to positions labelled with the result of the game they
+// came from. A score is turned into an expected result with a sigmoid, and the weights are moved
+// until the mean squared error between that and the real results is as small as it gets.
+
+use super::heuristic::{Heuristic, WEIGHT_COUNT};
+use crate::board::pdn::{PdnGame, PdnResult};
+use crate::board::{Board, Player};
+use anyhow::{Context, Result};
+use std::thread;
+
+// most steps fit_scale takes
+const SCALE_ROUNDS: u32 = 100;
+
+// a position and how the game went for the player to move, 1 a win, 0.5 a draw and 0 a loss
+#[derive(Debug, Clone)]
+pub struct LabelledPosition {
+    pub board: Board,
+    pub result: f64,
+}
+
+// The positions of finished games after the first `skip_plies` plies. Positions with a capture
+// to make are left out, the heuristic can't see how the trade ends.
+pub fn positions_from_games(games: &[PdnGame], skip_plies: usize) -> Result<Vec<LabelledPosition>> {
+    let mut positions = Vec::new();
+    for (i, game) in games.iter().enumerate() {
+        let black_result = match game.get_result() {
+            PdnResult::BlackWin => 1.0,
+            PdnResult::RedWin => 0.0,
+            PdnResult::Draw => 0.5,
+            PdnResult::Unfinished => continue,
+        };
+        let replay = game
+            .replay()
+            .with_context(|| format!("Could not replay game {}", i + 1))?;
+        for (b, _) in replay.into_iter().skip(skip_plies) {
+            if b.get_player_info().get_can_jump() {
+                continue;
+            }
+            let player = b.get_current_player();
+            let (black, red, kings) = b.get_bitboards();
+            positions.push(LabelledPosition {
+                // without the game's history, there can be a lot of these
+                board: Board::from_bitboards(black, red, kings, player)?,
+                result: match player {
+                    Player::Black => black_result,
+                    Player::Red => 1.0 - black_result,
+                },
+            });
+        }
+    }
+    Ok(positions)
+}
+
+// the expected result for the player to move, scale is how quickly a lead turns into a sure win
+fn sigmoid(score: i32, scale: f64) -> f64 {
+    1.0 / (1.0 + (-scale * score as f64).exp())
+}
+
+// mean squared error of the heuristic's expected results, computed on every core
+pub fn error(h_s: &Heuristic, positions: &[LabelledPosition], scale: f64) -> f64 {
+    if positions.is_empty() {
+        return 0.0;
+    }
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = positions.len().div_ceil(threads);
+    let total: f64 = thread::scope(|scope| {
+        let parts: Vec<_> = positions
+            .chunks(chunk)
+            .map(|part| {
+                scope.spawn(move || {
+                    part.iter()
+                        .map(|p| (p.result - sigmoid(h_s.h(&p.board, true), scale)).powi(2))
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+        parts
+            .into_iter()
+            .map(|part| part.join().expect("Err: error thread panicked"))
+            .sum()
+    });
+    total / positions.len() as f64
+}
+
+// The scale that fits the weights as they are best, found by stepping it up or down by a factor
+// that shrinks whenever neither direction helps. Tuning with it keeps the weights in the range
+// they already are.
+pub fn fit_scale(h_s: &Heuristic, positions: &[LabelledPosition]) -> f64 {
+    let mut best = 1e-4;
+    let mut best_error = error(h_s, positions, best);
+    let mut factor = 10.0_f64;
+    // when the results follow the scores exactly the error only gets smaller as the scale grows
+    for _ in 0..SCALE_ROUNDS {
+        if factor < 1.01 {
+            break;
+        }
+        let better = [best * factor, best / factor]
+            .into_iter()
+            .map(|scale| (scale, error(h_s, positions, scale)))
+            .find(|&(_, e)| e < best_error);
+        match better {
+            Some((scale, e)) => (best, best_error) = (scale, e),
+            None => factor = factor.sqrt(),
+        }
+    }
+    best
+}
+
+// Local search: every weight is tried a step up and a step down and moved if that lowers the
+// error. A weight's step starts at a tenth of it and halves whenever neither direction helps,
+// until every step is down to 1 or `passes` passes over the weights are done. Weights stay at 0
+// or above, like in Heuristic::mutate.
+#[cfg_attr(not(feature = "enable_print"), allow(unused_variables))]
+pub fn tune(h_s: &Heuristic, positions: &[LabelledPosition], scale: f64, passes: u32) -> Heuristic {
+    let mut weights = h_s.get_weights();
+    let mut steps = weights.map(|w| std::cmp::max(1, w / 10));
+    let mut best_error = error(h_s, positions, scale);
+    for pass in 0..passes {
+        let mut improved = false;
+        for i in 0..WEIGHT_COUNT {
+            let mut moved = false;
+            for step in [steps[i], -steps[i]] {
+                let mut tried = weights;
+                tried[i] = std::cmp::max(0, tried[i] + step);
+                if tried[i] == weights[i] {
+                    continue;
+                }
+                let e = error(&Heuristic::from_weights(tried), positions, scale);
+                if e < best_error {
+                    weights = tried;
+                    best_error = e;
+                    moved = true;
+                    break;
+                }
+            }
+            if moved {
+                improved = true;
+            } else if steps[i] > 1 {
+                steps[i] /= 2;
+                improved = true;
+            }
+        }
+        #[cfg(feature = "enable_print")]
+        {
+            eprintln!("Pass {}: error {:.6}", pass + 1, best_error);
+        }
+        if !improved {
+            break;
+        }
+    }
+    Heuristic::from_weights(weights)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::board::pdn::parse_pdn;
+
+    #[test]
+    fn test_positions_from_games() {
+        let games = parse_pdn(
+            r#"[Result "0-1"]
+1. 11-15 23-19 2. 8-11 22-17 0-1
+
+[Result "*"]
+1. 11-15 23-19 *
+"#,
+        )
+        .unwrap();
+        let positions = positions_from_games(&games, 1).unwrap();
+        // the unfinished game is left out, the first ply is skipped
+        assert_eq!(positions.len(), 3);
+        // red to move won, black to move lost
+        assert_eq!(positions[0].board.get_current_player(), Player::Red);
+        assert_eq!(positions[0].result, 1.0);
+        assert_eq!(positions[1].result, 0.0);
+    }
+
+    #[test]
+    fn test_tune_lowers_error() {
+        // the side with more men always wins, so with the value of a man far too low tuning has to
+        // raise it
+        let fens = [
+            ("B:W21,22,23:B9,10,11,12", 1.0),
+            ("W:W21,22,23:B9,10,11,12", 0.0),
+            ("B:W21,22,23,24:B10,11", 0.0),
+            ("W:W21,22,23,24:B10,11", 1.0),
+            ("B:W22,23:B6,7,10", 1.0),
+            ("W:W22,23:B6,7,10", 0.0),
+            ("B:W25,26,27:B5,6", 0.0),
+            ("W:W25,26,27:B5,6", 1.0),
+        ];
+        let positions: Vec<LabelledPosition> = fens
+            .iter()
+            .map(|&(fen, result)| LabelledPosition {
+                board: Board::from_fen(fen).unwrap(),
+                result,
+            })
+            .collect();
+        let mut weights = Heuristic::default_new().get_weights();
+        weights[0] = 100;
+        let start = Heuristic::from_weights(weights);
+        let scale = 1e-4;
+        let tuned = tune(&start, &positions, scale, 50);
+        assert!(error(&tuned, &positions, scale) < error(&start, &positions, scale));
+        assert!(tuned.get_weights()[0] > 100);
+        assert!(tuned.get_weights().iter().all(|&w| w >= 0));
+
+        let fitted = fit_scale(&tuned, &positions);
+        assert!(error(&tuned, &positions, fitted) <= error(&tuned, &positions, 1e-4));
+    }
+}
@@ -22,7 +22,13 @@ impl<T: Serialize> Tree<T> {
         Self { val, next: vec![] }
     }
 
+    #[allow(dead_code)]
     pub fn push(&mut self, next: Self) {
         self.next.push(Some(next));
     }
+
+    #[allow(dead_code)]
+    pub fn clear(&mut self) {
+        self.next.clear();
+    }
 }
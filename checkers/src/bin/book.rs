@@ -0,0 +1,122 @@
+// Builds an opening book, either from the games in PDN files or by searching the opening.
+use anyhow::{anyhow, bail, Context, Result};
+use checkers::ai::book::OpeningBook;
+use checkers::ai::engine::SearchLimits;
+use checkers::ai::heuristic::Heuristic;
+use checkers::board::pdn::parse_pdn;
+use checkers::board::Board;
+use std::fs::read_to_string;
+use std::time::Instant;
+
+const USAGE: &str = "Usage: book pdn <file>... [--plies <n>] [--out <path>]
+       book search [--plies <n>] [--lines <n>] [--depth <n>] [--weights <path>] [--out <path>]";
+const DEFAULT_PATH: &str = "book.bin";
+const DEFAULT_GAME_PLIES: usize = 12;
+const DEFAULT_SEARCH_PLIES: usize = 6;
+const DEFAULT_LINES: usize = 2;
+const DEFAULT_DEPTH: u32 = 10;
+
+enum Source {
+    Games(Vec<String>),
+    Search {
+        lines: usize,
+        depth: u32,
+        h_s: Heuristic,
+    },
+}
+
+struct Args {
+    source: Source,
+    plies: Option<usize>,
+    out: String,
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T> {
+    let value = value.ok_or_else(|| anyhow!("{} needs a value", flag))?;
+    value
+        .parse::<T>()
+        .map_err(|_| anyhow!("{} needs a number, not {}", flag, value))
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = std::env::args().skip(1);
+    let mode = args
+        .next()
+        .ok_or_else(|| anyhow!("Missing pdn or search"))?;
+    let mut files = Vec::new();
+    let mut plies = None;
+    let mut lines = DEFAULT_LINES;
+    let mut depth = DEFAULT_DEPTH;
+    let mut h_s = Heuristic::default_new();
+    let mut out = String::from(DEFAULT_PATH);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--plies" => plies = Some(parse_number(&arg, args.next())?),
+            "--lines" => lines = parse_number(&arg, args.next())?,
+            "--depth" => depth = parse_number(&arg, args.next())?,
+            "--weights" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--weights needs a path"))?;
+                h_s = Heuristic::load(&path)?;
+            }
+            "--out" => out = args.next().ok_or_else(|| anyhow!("--out needs a path"))?,
+            _ if mode == "pdn" && !arg.starts_with("--") => files.push(arg),
+            _ => bail!("Unexpected argument {}", arg),
+        }
+    }
+    let source = match mode.as_str() {
+        "pdn" if files.is_empty() => bail!("Missing the PDN files"),
+        "pdn" => Source::Games(files),
+        "search" if lines == 0 => bail!("--lines needs at least one line"),
+        "search" => Source::Search { lines, depth, h_s },
+        _ => bail!("Unknown mode {}", mode),
+    };
+    Ok(Args { source, plies, out })
+}
+
+fn build(args: &Args) -> Result<OpeningBook> {
+    match &args.source {
+        Source::Games(files) => {
+            let mut games = Vec::new();
+            for file in files {
+                let text =
+                    read_to_string(file).with_context(|| format!("Could not read {}", file))?;
+                games.extend(parse_pdn(&text).with_context(|| format!("In {}", file))?);
+            }
+            println!("Read {} games", games.len());
+            OpeningBook::from_games(&games, args.plies.unwrap_or(DEFAULT_GAME_PLIES))
+        }
+        Source::Search { lines, depth, h_s } => Ok(OpeningBook::from_search(
+            &Board::default(),
+            args.plies.unwrap_or(DEFAULT_SEARCH_PLIES),
+            *lines,
+            &SearchLimits::from_depth(*depth),
+            h_s,
+        )),
+    }
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("Error: {:#}\n{}", e, USAGE);
+            std::process::exit(1);
+        }
+    };
+    let now = Instant::now();
+    let book = match build(&args).and_then(|book| book.save(&args.out).map(|_| book)) {
+        Ok(book) => book,
+        Err(e) => {
+            println!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "Saved {} positions to {} in {:.3}s",
+        book.len(),
+        args.out,
+        now.elapsed().as_secs_f64()
+    );
+}
@@ -0,0 +1,68 @@
+use anyhow::{anyhow, bail, Result};
+use checkers::board::Board;
+use std::time::Instant;
+
+const USAGE: &str = "Usage: perft <depth> [--divide] [--fen <fen>]";
+
+struct Args {
+    depth: u32,
+    divide: bool,
+    board: Board,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut depth = None;
+    let mut divide = false;
+    let mut board = Board::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--divide" => divide = true,
+            "--fen" => {
+                let fen = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--fen needs a position"))?;
+                board = Board::from_fen(&fen)?;
+            }
+            _ => match arg.parse::<u32>() {
+                Ok(d) if depth.is_none() => depth = Some(d),
+                _ => bail!("Unexpected argument {}", arg),
+            },
+        }
+    }
+    Ok(Args {
+        depth: depth.ok_or_else(|| anyhow!("Missing depth"))?,
+        divide,
+        board,
+    })
+}
+
+fn main() {
+    let mut args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("Error: {}\n{}", e, USAGE);
+            std::process::exit(1);
+        }
+    };
+    println!("{}", args.board);
+
+    let now = Instant::now();
+    let nodes = if args.divide {
+        let divide = args.board.perft_divide(args.depth);
+        for (mv, nodes) in divide.iter() {
+            println!("{}: {}", mv, nodes);
+        }
+        divide.iter().map(|(_, n)| n).sum()
+    } else {
+        args.board.perft(args.depth)
+    };
+    let elapsed = now.elapsed();
+    println!(
+        "perft({}) = {} in {:.3}s ({:.0} nodes/s)",
+        args.depth,
+        nodes,
+        elapsed.as_secs_f64(),
+        nodes as f64 / elapsed.as_secs_f64().max(1e-9)
+    );
+}
@@ -0,0 +1,407 @@
+// A line based protocol on stdin/stdout so other programs (GUIs, test harnesses) can drive the
+// engine, in the spirit of the UCI and Hub protocols. The search's own printing goes to stderr, so
+// stdout only ever carries the protocol. The arguments --threads <n> and --weights <path> set the
+// number of search threads and the heuristic weights, --tablebase <path> loads endgame tables made
+// by the tablebase binary and --book <path> an opening book made by the book binary, played best
+// first or with --book-selection weighted at random. --draw-moves <n> sets how many moves each side
+// may make without a capture or a man moving before the game is drawn. --seed <n> makes the
+// engine's random choices repeatable and --no-noise turns off the noise in the evaluation, so a
+// search with a depth or node limit on one thread always gives the same result.
+//
+//   init                                  answers with the engine's id lines and initok
+//   isready                               answers readyok
+//   position startpos [moves <move>...]
+//   position fen <fen> [moves <move>...]
+//   position file <path> [moves <move>...] a board file or the last position of a PDN game
+//   moves <move>...                       plays the moves on the current position
+//   go [movetime <ms>] [depth <n>] [nodes <n>] [infinite]
+//   stop                                  ends the search, its best move is still reported
+//   fen                                   prints the current position
+//   weights <path>                        searches with the heuristic weights in a JSON file
+//   quit
+//
+// A search prints a line for every finished depth,
+//   info depth <d> score cp <score>|win <plies>|loss <plies> nodes <n> nps <n> time <ms> pv <move>...
+// and ends with bestmove <move>, or bestmove none after an info string saying how the game ended
+// when it is already over. Errors are reported as info string error: <message>.
+
+use anyhow::{anyhow, bail, Context, Result};
+use checkers::ai::book::{BookSelection, OpeningBook};
+use checkers::ai::engine::{AlphaBeta, Engine, SearchLimits, SearchResult};
+use checkers::ai::heuristic::Heuristic;
+use checkers::ai::tablebase::Tablebase;
+use checkers::board::pdn::PdnGame;
+use checkers::board::{Board, DrawReason, GameOutcome, DEFAULT_DRAW_MOVE_LIMIT};
+use std::fs::read_to_string;
+use std::io::stdin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const POSITION_USAGE: &str = "Usage: position startpos|fen <fen>|file <path> [moves <move>...]";
+
+struct Search {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    // whether the search ends by itself
+    limited: bool,
+}
+
+impl Search {
+    // stops the search and waits until it has printed its best move
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait();
+    }
+
+    // a search that ends by itself is waited for, an infinite one is stopped
+    fn finish(self) {
+        match self.limited {
+            true => self.wait(),
+            false => self.stop(),
+        }
+    }
+
+    // a search that went wrong is reported, the session goes on
+    fn wait(self) {
+        if self.handle.join().is_err() {
+            println!("info string error: the search thread panicked");
+        }
+    }
+}
+
+struct Session {
+    board: Board,
+    engine: AlphaBeta,
+    tablebase: Option<Arc<Tablebase>>,
+    book: Option<Arc<OpeningBook>>,
+    seed: Option<u64>,
+    noise: bool,
+    draw_moves: u32,
+    search: Option<Search>,
+}
+
+impl Session {
+    fn new(args: Args) -> Self {
+        let mut board = Board::default();
+        board.set_draw_move_limit(args.draw_moves);
+        let mut session = Self {
+            board,
+            engine: AlphaBeta::default(),
+            tablebase: args.tablebase.map(Arc::new),
+            book: args.book.map(Arc::new),
+            seed: args.seed,
+            noise: args.noise,
+            draw_moves: args.draw_moves,
+            search: None,
+        };
+        session.set_engine(args.h_s, args.threads);
+        session
+    }
+
+    fn set_engine(&mut self, h_s: Heuristic, threads: usize) {
+        self.engine = AlphaBeta::new(h_s);
+        self.engine.set_threads(threads);
+        if let Some(tablebase) = &self.tablebase {
+            self.engine.set_tablebase(Arc::clone(tablebase));
+        }
+        if let Some(book) = &self.book {
+            self.engine.set_book(Arc::clone(book));
+        }
+        // every search runs on a copy of the engine, so each one starts from the same seed
+        if let Some(seed) = self.seed {
+            self.engine.set_seed(seed);
+        }
+        self.engine.set_noise(self.noise);
+    }
+
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop();
+        }
+    }
+
+    fn finish_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.finish();
+        }
+    }
+
+    // returns false once the session should end
+    fn handle(&mut self, line: &str) -> Result<bool> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["init"] => {
+                println!("id name Checkers");
+                println!("id author Gregory Presser");
+                println!("initok");
+            }
+            ["isready"] => println!("readyok"),
+            ["position", args @ ..] => {
+                self.finish_search();
+                self.board = parse_position(args)?;
+                self.board.set_draw_move_limit(self.draw_moves);
+            }
+            ["moves", moves @ ..] => {
+                self.finish_search();
+                play_moves(&mut self.board, moves)?;
+            }
+            ["go", args @ ..] => {
+                self.finish_search();
+                let limits = parse_limits(args)?;
+                self.search = Some(self.go(limits));
+            }
+            ["stop"] => self.stop_search(),
+            ["fen"] => println!("{}", self.board.to_fen()),
+            ["weights", path] => {
+                self.finish_search();
+                let threads = self.engine.get_threads();
+                self.set_engine(Heuristic::load(path)?, threads);
+            }
+            ["quit"] => {
+                self.stop_search();
+                return Ok(false);
+            }
+            [command, ..] => bail!("Unknown command {}", command),
+        }
+        Ok(true)
+    }
+
+    // searches on its own thread so stop can still be read while it runs
+    fn go(&self, mut limits: SearchLimits) -> Search {
+        let limited = limits.time.is_some() || limits.depth.is_some() || limits.nodes.is_some();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+        limits.stop = Some(Arc::clone(&stop));
+        limits.info = Some(tx);
+        let b = self.board.clone();
+        let mut engine = self.engine.clone();
+        let handle = thread::spawn(move || {
+            if let Some(outcome) = b.is_game_over() {
+                println!("info string {}", game_over_text(&b, outcome));
+                println!("bestmove none");
+                return;
+            }
+            let search = {
+                let b = b.clone();
+                thread::spawn(move || engine.choose_move(&b, limits))
+            };
+            // ends once the search is done and has dropped its end of the channel
+            for info in rx {
+                println!("{}", info_line(&b, &info));
+            }
+            match search.join() {
+                Ok(result) => println!(
+                    "bestmove {}",
+                    b.get_player_info().get_moves()[result.best_move].notation()
+                ),
+                Err(_) => {
+                    println!("info string error: the search failed");
+                    println!("bestmove none");
+                }
+            }
+        });
+        Search {
+            stop,
+            handle,
+            limited,
+        }
+    }
+}
+
+fn game_over_text(b: &Board, outcome: GameOutcome) -> String {
+    match outcome {
+        GameOutcome::Win(winner) => format!("game over, {:?} wins", winner),
+        GameOutcome::Draw(DrawReason::Repetition) => {
+            String::from("game over, drawn by threefold repetition")
+        }
+        GameOutcome::Draw(DrawReason::NoProgress) => {
+            format!(
+                "game over, drawn after {} moves without a capture or a man moving",
+                b.get_draw_move_limit()
+            )
+        }
+    }
+}
+
+fn parse_position(args: &[&str]) -> Result<Board> {
+    let (mut b, rest) = match args {
+        ["startpos", rest @ ..] => (Board::default(), rest),
+        ["fen", fen, rest @ ..] => (Board::from_fen(fen)?, rest),
+        ["file", path, rest @ ..] => {
+            let file = read_to_string(path).with_context(|| format!("Could not read {}", path))?;
+            let b = if file.trim_start().starts_with('[') {
+                file.parse::<PdnGame>()?.final_board()?
+            } else {
+                file.parse::<Board>()?
+            };
+            (b, rest)
+        }
+        _ => bail!(POSITION_USAGE),
+    };
+    match rest {
+        [] => {}
+        ["moves", moves @ ..] => play_moves(&mut b, moves)?,
+        _ => bail!(POSITION_USAGE),
+    }
+    Ok(b)
+}
+
+// either every move is played or, if one of them is illegal, none
+fn play_moves(b: &mut Board, moves: &[&str]) -> Result<()> {
+    let mut next = b.clone();
+    for text in moves {
+        let mv = next
+            .parse_move(text)
+            .with_context(|| format!("Could not play {}", text))?;
+        next.do_move(mv);
+    }
+    *b = next;
+    Ok(())
+}
+
+fn parse_limits(args: &[&str]) -> Result<SearchLimits> {
+    let mut limits = SearchLimits::default();
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        if arg == "infinite" {
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| anyhow!("{} needs a value", arg))?
+            .parse::<u64>()
+            .with_context(|| format!("{} needs a number", arg))?;
+        match arg {
+            "movetime" => limits.time = Some(Duration::from_millis(value)),
+            "depth" => limits.depth = Some(value as u32),
+            "nodes" => limits.nodes = Some(value),
+            _ => bail!("Unknown search limit {}", arg),
+        }
+    }
+    Ok(limits)
+}
+
+fn info_line(b: &Board, result: &SearchResult) -> String {
+    let score = match result.mate_in() {
+        Some(plies) if plies > 0 => format!("win {}", plies),
+        Some(plies) => format!("loss {}", -plies),
+        None => format!("cp {}", result.score.unwrap_or(0)),
+    };
+    format!(
+        "info depth {} score {} nodes {} nps {} time {} pv {}",
+        result.depth,
+        score,
+        result.nodes,
+        result.nps(),
+        result.time.as_millis(),
+        result.pv_notation(b).join(" ")
+    )
+}
+
+struct Args {
+    threads: usize,
+    h_s: Heuristic,
+    tablebase: Option<Tablebase>,
+    book: Option<OpeningBook>,
+    seed: Option<u64>,
+    noise: bool,
+    draw_moves: u32,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut h_s = Heuristic::default_new();
+    let mut tablebase = None;
+    let mut book = None;
+    let mut selection = BookSelection::Best;
+    let mut seed = None;
+    let mut noise = true;
+    let mut draw_moves = DEFAULT_DRAW_MOVE_LIMIT;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--no-noise" {
+            noise = false;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| anyhow!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--threads" => {
+                threads = value
+                    .parse::<usize>()
+                    .with_context(|| format!("--threads needs a number, not {}", value))?
+            }
+            "--weights" => h_s = Heuristic::load(&value)?,
+            "--tablebase" => tablebase = Some(Tablebase::load(&value)?),
+            "--book" => book = Some(OpeningBook::load(&value)?),
+            "--seed" => {
+                seed = Some(
+                    value
+                        .parse::<u64>()
+                        .with_context(|| format!("--seed needs a number, not {}", value))?,
+                )
+            }
+            "--draw-moves" => {
+                draw_moves = value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .with_context(|| {
+                        format!("--draw-moves needs a positive number, not {}", value)
+                    })?
+            }
+            "--book-selection" => {
+                selection = match value.as_str() {
+                    "best" => BookSelection::Best,
+                    "weighted" => BookSelection::Weighted,
+                    _ => bail!("--book-selection is best or weighted, not {}", value),
+                }
+            }
+            _ => bail!("Unexpected argument {}", arg),
+        }
+    }
+    if let Some(book) = book.as_mut() {
+        book.set_selection(selection);
+    }
+    Ok(Args {
+        threads,
+        h_s,
+        tablebase,
+        book,
+        seed,
+        noise,
+        draw_moves,
+    })
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!(
+                "Error: {:#}\nUsage: protocol [--threads <n>] [--weights <path>] [--tablebase <path>] [--book <path>] [--book-selection best|weighted] [--seed <n>] [--draw-moves <n>] [--no-noise]",
+                e
+            );
+            std::process::exit(1);
+        }
+    };
+    let mut session = Session::new(args);
+    for line in stdin().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        match session.handle(&line) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => println!("info string error: {:#}", e),
+        }
+    }
+    // the input is closed, a search that ends by itself is still let finish
+    session.finish_search();
+}
@@ -0,0 +1,67 @@
+use anyhow::{anyhow, bail, Result};
+use checkers::ai::tablebase::Tablebase;
+use std::time::Instant;
+
+const USAGE: &str = "Usage: tablebase <max pieces> [--out <path>]";
+const DEFAULT_PATH: &str = "tablebase.bin";
+
+struct Args {
+    max_pieces: u32,
+    out: String,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut max_pieces = None;
+    let mut out = String::from(DEFAULT_PATH);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = args.next().ok_or_else(|| anyhow!("--out needs a path"))?,
+            _ => match arg.parse::<u32>() {
+                Ok(n) if max_pieces.is_none() => max_pieces = Some(n),
+                _ => bail!("Unexpected argument {}", arg),
+            },
+        }
+    }
+    Ok(Args {
+        max_pieces: max_pieces.ok_or_else(|| anyhow!("Missing the number of pieces"))?,
+        out,
+    })
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("Error: {}\n{}", e, USAGE);
+            std::process::exit(1);
+        }
+    };
+    let now = Instant::now();
+    let tablebase = match Tablebase::generate(args.max_pieces) {
+        Ok(tablebase) => tablebase,
+        Err(e) => {
+            println!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let elapsed = now.elapsed();
+    // material is written as the side to move's men and kings against the other side's
+    println!(
+        "{:<14} {:>10} {:>10} {:>10}",
+        "material", "wins", "losses", "draws"
+    );
+    for (material, wins, losses, draws) in tablebase.summary() {
+        println!("{:<14} {:>10} {:>10} {:>10}", material, wins, losses, draws);
+    }
+    println!(
+        "Solved every position with up to {} pieces in {:.3}s",
+        args.max_pieces,
+        elapsed.as_secs_f64()
+    );
+    if let Err(e) = tablebase.save(&args.out) {
+        println!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+    println!("Saved to {}", args.out);
+}
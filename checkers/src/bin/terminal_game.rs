@@ -1,49 +1,77 @@
-use checkers::ai::{heuristic::Heuristic, predict_move};
-use checkers::board::{Board, Player};
-use std::fs::read_to_string;
+use anyhow::anyhow;
+use checkers::ai::book::{BookSelection, OpeningBook};
+use checkers::ai::engine::{AlphaBeta, Engine, Human, RandomMover, SearchLimits};
+use checkers::ai::heuristic::Heuristic;
+use checkers::ai::mcts::{Mcts, Playout};
+use checkers::ai::tablebase::Tablebase;
+use checkers::board::pdn::PdnGame;
+use checkers::board::{Board, DrawReason, GameOutcome, Player};
+use std::fs::{read_to_string, write};
 use std::io::stdin;
+use std::sync::Arc;
 
-type AutomatedMoveFinder = fn(Board, time_in_sec: u32, Option<Heuristic>) -> usize;
-type ManualMoveFinder = fn() -> usize;
-
-enum MoveFinder {
-    Automated(AutomatedMoveFinder),
-    Manual(ManualMoveFinder),
-}
-
-fn game_loop(b: &mut Board, red_mover: MoveFinder, black_mover: MoveFinder, time_limit: u32) {
+fn game_loop(
+    b: &mut Board,
+    red: &mut Box<dyn Engine>,
+    black: &mut Box<dyn Engine>,
+    time_limit: u32,
+    record: &mut PdnGame,
+) {
     let mut is_game_over = b.is_game_over();
-    while let None = is_game_over {
+    while is_game_over.is_none() {
         println!("{:}", b);
         b.print_moves();
-        let mv = match b.get_current_player() {
-            Player::Red => &red_mover,
-            Player::Black => &black_mover,
+        let engine = match b.get_current_player() {
+            Player::Red => &mut *red,
+            Player::Black => &mut *black,
         };
 
-        loop {
-            let m = match mv {
-                MoveFinder::Manual(f) => f(),
-                MoveFinder::Automated(f) => f(b.clone(), time_limit, None),
-            };
-            if b.do_move(m) {
-                println!("Move {} was chosen", m);
-                break;
-            }
-            println!("Please Enter a Number in the range");
-            println!("You Tried to do {:?}", m);
+        let result = engine.choose_move(b, SearchLimits::from_secs(time_limit as u64));
+        if result.resign {
+            let winner = b.get_current_player().get_other();
+            println!("{} resigns, Player {:?} wins", engine.name(), winner);
+            record.set_result(Some(GameOutcome::Win(winner)).into());
+            return;
         }
+        let m = result.best_move;
+        println!(
+            "Move {} was chosen by {}",
+            b.get_player_info().get_moves()[m].notation(),
+            engine.name()
+        );
+        // a move looked up in the tablebase has a score without any search behind it
+        if result.nodes > 0 || result.score.is_some() {
+            println!("{}", result.analysis(b));
+        }
+        record.push_move(b, m);
+        b.do_move(m);
         is_game_over = b.is_game_over();
     }
     println!("Game Over!");
     println!("{:}", b);
-    println!("Player {:?} wins", is_game_over.expect("Unrechable"));
+    record.set_result(is_game_over.into());
+    match is_game_over.expect("Unrechable") {
+        GameOutcome::Win(winner) => println!("Player {:?} wins", winner),
+        GameOutcome::Draw(DrawReason::Repetition) => {
+            println!("Draw, the same position came up three times")
+        }
+        GameOutcome::Draw(DrawReason::NoProgress) => {
+            println!(
+                "Draw, no captures or man moves in the last {} moves",
+                b.get_draw_move_limit()
+            )
+        }
+    }
 }
 
 fn read_number(input: &str) -> u32 {
     println!("{:}", input);
     let mut s = String::new();
     match stdin().read_line(&mut s) {
+        Ok(0) => {
+            println!("Error: input closed");
+            std::process::exit(1);
+        }
         Err(_) => {
             println!("Please Enter a Valid Number");
             read_number(input)
@@ -66,14 +94,12 @@ fn read_number(input: &str) -> u32 {
     }
 }
 
-fn read_user_input() -> usize {
-    read_number("Please Pick a Move\nNote: jumps:{} contains which pieces will be jumped during that move (not necessarily in that order)") as usize
-}
-
 fn confirm(input: &str) -> bool {
     println!("{:}", input);
     let mut s = String::new();
     match stdin().read_line(&mut s) {
+        // nobody is left to answer
+        Ok(0) => false,
         Err(_) => {
             println!("Invalid Input");
             confirm(input)
@@ -98,39 +124,193 @@ fn confirm(input: &str) -> bool {
     }
 }
 
-fn get_single_game_mode(input: &str) -> MoveFinder {
-    match confirm(input) {
-        true => MoveFinder::Manual(read_user_input),
-        false => MoveFinder::Automated(predict_move),
+// alpha_beta is set up from the arguments, each side that picks it gets a copy. A seed makes the
+// engine's random choices the same every game.
+fn get_engine(
+    player: &str,
+    h_s: &Heuristic,
+    alpha_beta: &AlphaBeta,
+    seed: Option<u64>,
+) -> Box<dyn Engine> {
+    let mut engine: Box<dyn Engine> = match read_number(&format!(
+        "Who should play for {}? (0: you, 1: Alpha/Beta AI, 2: random moves, 3: MCTS AI, 4: MCTS AI with heuristic playouts)",
+        player
+    )) {
+        0 => Box::new(Human::new(h_s.clone())),
+        1 => Box::new(alpha_beta.clone()),
+        2 => Box::new(RandomMover::default()),
+        3 => Box::new(Mcts::default()),
+        4 => Box::new(Mcts::new(Playout::Heuristic(h_s.clone()))),
+        _ => {
+            println!("Please Enter one of the listed numbers");
+            return get_engine(player, h_s, alpha_beta, seed);
+        }
+    };
+    if let Some(seed) = seed {
+        engine.set_seed(seed);
     }
+    engine
 }
 
-fn get_game_mode() -> (MoveFinder, MoveFinder) {
+// with --seed the two sides get different seeds, so two random movers don't mirror each other
+fn get_game_mode(
+    h_s: &Heuristic,
+    alpha_beta: &AlphaBeta,
+    seed: Option<u64>,
+) -> (Box<dyn Engine>, Box<dyn Engine>) {
     (
-        get_single_game_mode("Would you like to play for red (y/n)"),
-        get_single_game_mode("Would you like to play for Black (y/n)"),
+        get_engine("Red", h_s, alpha_beta, seed),
+        get_engine(
+            "Black",
+            h_s,
+            alpha_beta,
+            seed.map(|seed| seed.wrapping_add(1)),
+        ),
     )
 }
 
-fn get_init_board() -> Option<String> {
-    match confirm("Would you like to Input a Board Path (y/n)") {
-        true => {
-            let mut path = String::new();
-            fn read_path(inner_s: &mut String) {
-                println!("Please Input a Valid Path");
-                if stdin().read_line(inner_s).is_err() {
-                    println!("Error Reading Input");
-                    read_path(inner_s)
-                }
+// --seed <n> makes the engines' random choices the same in every game. Searches stopped by time
+// still differ from run to run.
+fn get_seed() -> Option<u64> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            match args.next().and_then(|n| n.parse::<u64>().ok()) {
+                Some(seed) => return Some(seed),
+                None => println!("Error: --seed needs a number, playing without one"),
             }
-            read_path(&mut path);
-            if let Some('\n') = path.chars().next_back() {
-                path.pop();
+        }
+    }
+    None
+}
+
+// --draw-moves <n> changes how many moves each side may make without a capture or a man moving
+// before the game is drawn
+fn get_draw_moves() -> Option<u32> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--draw-moves" {
+            match args.next().and_then(|n| n.parse::<u32>().ok()) {
+                Some(n) if n > 0 => return Some(n),
+                _ => println!("Error: --draw-moves needs a positive number, using the default"),
             }
-            if let Some('\r') = path.chars().next_back() {
-                path.pop();
+        }
+    }
+    None
+}
+
+// the number of search threads can be given with --threads, by default every core is used
+fn get_threads() -> usize {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--threads" {
+            match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n > 0 => return n,
+                _ => println!("Error: --threads needs a positive number, using every core"),
             }
+        }
+    }
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// heuristic weights can be loaded from a JSON file with --weights, see Heuristic
+fn get_weights() -> Heuristic {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--weights" {
+            let loaded = args
+                .next()
+                .ok_or_else(|| anyhow!("--weights needs a path"))
+                .and_then(|path| Heuristic::load(&path));
+            match loaded {
+                Ok(h_s) => return h_s,
+                Err(e) => {
+                    println!("Error: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    Heuristic::default_new()
+}
+
+// endgame tables made by the tablebase binary can be loaded with --tablebase
+fn get_tablebase() -> Option<Tablebase> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--tablebase" {
+            let loaded = args
+                .next()
+                .ok_or_else(|| anyhow!("--tablebase needs a path"))
+                .and_then(|path| Tablebase::load(&path));
+            match loaded {
+                Ok(tablebase) => return Some(tablebase),
+                Err(e) => {
+                    println!("Error: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    None
+}
+
+// an opening book made by the book binary can be loaded with --book, its moves are played best
+// first unless --book-selection weighted is given
+fn get_book() -> Option<OpeningBook> {
+    let mut path = None;
+    let mut selection = BookSelection::Best;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--book" => path = args.next(),
+            "--book-selection" => match args.next().as_deref() {
+                Some("best") => selection = BookSelection::Best,
+                Some("weighted") => selection = BookSelection::Weighted,
+                _ => println!("Error: --book-selection is best or weighted, using best"),
+            },
+            _ => {}
+        }
+    }
+    match OpeningBook::load(&path?) {
+        Ok(mut book) => {
+            book.set_selection(selection);
+            Some(book)
+        }
+        Err(e) => {
+            println!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_path() -> String {
+    let mut path = String::new();
+    println!("Please Input a Valid Path");
+    match stdin().read_line(&mut path) {
+        Ok(0) => {
+            println!("Error: input closed");
+            std::process::exit(1);
+        }
+        Err(_) => {
+            println!("Error Reading Input");
+            return read_path();
+        }
+        Ok(_) => {}
+    }
+    if let Some('\n') = path.chars().next_back() {
+        path.pop();
+    }
+    if let Some('\r') = path.chars().next_back() {
+        path.pop();
+    }
+    path
+}
 
+fn get_init_board() -> Option<String> {
+    match confirm("Would you like to Input a Board or PDN Path (y/n)") {
+        true => {
+            let path = read_path();
             match read_to_string(&path) {
                 Err(_) => {
                     println!(
@@ -146,8 +326,26 @@ fn get_init_board() -> Option<String> {
     }
 }
 
+fn is_pdn(file: &str) -> bool {
+    file.trim_start().starts_with('[')
+}
+
+// the game and its last position, or None after printing why the game can't be continued
+fn load_pdn(file: &str) -> Option<(Board, PdnGame)> {
+    let loaded = file
+        .parse::<PdnGame>()
+        .and_then(|game| game.final_board().map(|b| (b, game)));
+    match loaded {
+        Ok(loaded) => Some(loaded),
+        Err(e) => {
+            println!("Error: {:#}, Creating a Default Board", e);
+            None
+        }
+    }
+}
+
 fn get_time_limit(init: &Option<String>) -> u32 {
-    match init {
+    match init.as_ref().filter(|fs| !is_pdn(fs)) {
         Some(fs) => match fs.lines().nth(9) {
             Some(s) => s.parse::<u32>().unwrap_or_else(|_| {
                 println!("Error Reading file could not parse 9th line to u32");
@@ -173,15 +371,69 @@ fn offer_swap(b: &mut Board) {
     }
 }
 
+fn offer_save(record: &PdnGame) {
+    if !confirm("Would you like to save the game as PDN (y/n)") {
+        return;
+    }
+    let path = read_path();
+    if let Err(e) = write(&path, format!("{}", record)) {
+        println!("Error: Could not write {:}: {:}", path, e);
+        offer_save(record);
+    }
+}
+
 fn main() {
+    let h_s = get_weights();
+    let mut alpha_beta = AlphaBeta::new(h_s.clone());
+    alpha_beta.set_threads(get_threads());
+    if let Some(tablebase) = get_tablebase() {
+        alpha_beta.set_tablebase(Arc::new(tablebase));
+    }
+    if let Some(book) = get_book() {
+        alpha_beta.set_book(Arc::new(book));
+    }
     let init = get_init_board();
-    let mut b = if let Some(ref init_b) = init {
-        init_b.parse().unwrap()
-    } else {
-        Board::default()
+    // a PDN game is continued from its last position, anything else is read as a board file
+    let loaded = match init {
+        Some(ref init_b) if is_pdn(init_b) => load_pdn(init_b),
+        _ => None,
     };
-    offer_swap(&mut b);
-    let (red, black) = get_game_mode();
+    let (mut b, mut record) = match loaded {
+        Some(loaded) => loaded,
+        None => {
+            let mut b = match init.as_ref().filter(|init_b| !is_pdn(init_b)) {
+                Some(init_b) => init_b.parse().unwrap_or_else(|e| {
+                    println!("Error: {:#}, Creating a Default Board", e);
+                    Board::default()
+                }),
+                None => Board::default(),
+            };
+            offer_swap(&mut b);
+            let record = PdnGame::new(&b);
+            (b, record)
+        }
+    };
+    if let Some(moves) = get_draw_moves() {
+        b.set_draw_move_limit(moves);
+    }
+    let (mut red, mut black) = get_game_mode(&h_s, &alpha_beta, get_seed());
     let time_limit = get_time_limit(&init);
-    game_loop(&mut b, red, black, time_limit);
+    game_loop(&mut b, &mut red, &mut black, time_limit, &mut record);
+    offer_save(&record);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_load_pdn() {
+        let (b, game) = load_pdn("[Event \"test\"]\n1. 11-15 23-19 *").unwrap();
+        assert_eq!(game.final_board().unwrap().to_fen(), b.to_fen());
+        assert_eq!(b.get_current_player(), Player::Black);
+
+        // an illegal move or a broken tag falls back to a new game instead of panicking
+        assert!(load_pdn("[Event \"test\"]\n1. 11-15 11-15 *").is_none());
+        assert!(load_pdn("[Event \"test\"\n1. 11-15 *").is_none());
+    }
 }
@@ -1,129 +1,298 @@
-use checkers::ai::{heuristic::Heuristic, predict_move};
+// A genetic algorithm for the heuristic weights. Every generation the whole population plays a
+// round robin, each pairing several games with both colours, and an individual's fitness is its
+// share of the points (2 for a win, 1 for a draw). The best few go on unchanged, the rest of the
+// next generation are crossovers of parents picked by tournament, mutated a little.
+use anyhow::{anyhow, bail, Context, Result};
+use checkers::ai::engine::{AlphaBeta, Engine, SearchLimits};
+use checkers::ai::heuristic::Heuristic;
+use checkers::ai::new_rng;
+use checkers::board::pdn::{PdnGame, PdnResult};
 use checkers::board::{Board, Player};
-use std::sync::{Arc, RwLock};
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 use std::thread;
+use std::time::Duration;
 
-type GameResult = (u32, Heuristic);
+const USAGE: &str = "Usage: train [--population <n>] [--generations <n>] [--games <n>] [--elite <n>] [--time <ms>] [--threads <n>] [--weights <path>] [--seed <n>]";
+// every training game is appended here so it can be replayed later
+const GAMES_FILE: &str = "train_games.pdn";
+// the best individual of the last finished generation, it can be loaded with --weights
+const WEIGHTS_FILE: &str = "train_weights.json";
+// a game still going after this many plies is scored as a draw
+const MAX_PLIES: usize = 300;
+// parents are the best of this many individuals picked at random
+const TOURNAMENT_SIZE: usize = 3;
 
-const TIME_LIMIT: u32 = 5;
+struct Args {
+    population: usize,
+    generations: u32,
+    // games per pairing with each colour
+    games: usize,
+    // individuals carried over to the next generation unchanged
+    elite: usize,
+    move_time: Duration,
+    // games played at the same time
+    threads: usize,
+    start: Heuristic,
+    seed: Option<u64>,
+}
 
-fn game_loop(
-    red_h: Heuristic,
-    black_h: Heuristic,
-    child_num: u32,
-    time_to_beat: &Arc<RwLock<u32>>,
-) -> GameResult {
-    let mut b = Board::default();
-    if child_num % 2 == 0 {
-        b.swap_current_player();
-    }
-    let mut is_game_over = b.is_game_over();
-    let mut red_counter = 0;
-    let mut black_counter = 0;
-    let mut time_since_last_jump = 0;
-    while let None = is_game_over {
-        if (red_counter + black_counter) % 20 == 0 {
-            println!(
-                "The players in game {} have done ({},{}) moves, time since last jump is {}",
-                child_num, red_counter, black_counter, time_since_last_jump
-            );
-        }
-        if (red_counter + black_counter) % 40 == 0 && red_counter != 0 {
-            println!("board for child {}\n{}", child_num, b);
-        }
-        let m = match b.get_current_player() {
-            Player::Red => {
-                red_counter += 1;
-                predict_move(b.clone(), TIME_LIMIT, Option::Some(red_h.clone()))
-            }
-            Player::Black => {
-                black_counter += 1;
-                predict_move(b.clone(), TIME_LIMIT, Option::Some(black_h.clone()))
-            }
-        };
-        if b.get_player_info().get_moves()[m].is_jump() {
-            time_since_last_jump = 0;
-        } else {
-            time_since_last_jump += 1;
-        }
-        {
-            let t = time_to_beat.read().expect("Poisned Lock");
-            if (red_counter + black_counter) % 60 == 0 {
-                println!("t = {}", *t);
-            }
-            if time_since_last_jump > 50 || (std::cmp::max(black_counter, red_counter) > *t) {
-                break;
-            }
-        }
+// one game of the round robin, black and red are indices into the population
+struct Game {
+    black: usize,
+    red: usize,
+    seed: u64,
+}
 
-        b.do_move(m);
+fn play_game(
+    population: &[Heuristic],
+    game: &Game,
+    move_time: Duration,
+    generation: u32,
+) -> PdnGame {
+    let mut rng = new_rng(Some(game.seed));
+    let mut black = AlphaBeta::new(population[game.black].clone());
+    black.set_seed(rng.gen());
+    let mut red = AlphaBeta::new(population[game.red].clone());
+    red.set_seed(rng.gen());
 
-        is_game_over = b.is_game_over();
+    let mut b = Board::default();
+    let mut record = PdnGame::new(&b);
+    record.set_tag("Event", &format!("Training generation {}", generation));
+    record.set_tag("Black", &format!("h{}", game.black));
+    record.set_tag("White", &format!("h{}", game.red));
+    // depth and speed of every search, to see how well the engines searched in this game
+    let mut depths = 0;
+    let mut nodes = 0;
+    let mut search_time = 0.0;
+    let mut plies = 0;
+    while b.is_game_over().is_none() && plies < MAX_PLIES {
+        let limits = SearchLimits {
+            time: Some(move_time),
+            ..Default::default()
+        };
+        let result = match b.get_current_player() {
+            Player::Black => black.choose_move(&b, limits),
+            Player::Red => red.choose_move(&b, limits),
+        };
+        depths += result.depth;
+        nodes += result.nodes;
+        search_time += result.time.as_secs_f64();
+        record.push_move(&b, result.best_move);
+        b.do_move(result.best_move);
+        plies += 1;
     }
-
+    // a game cut off at MAX_PLIES stays unfinished, it is scored as a draw
+    record.set_result(PdnResult::from(b.is_game_over()));
     println!(
-        "Game {} finished in ~{} moves. winner? {:?}",
-        child_num,
-        std::cmp::max(red_counter, black_counter),
-        is_game_over
+        "h{} (black) vs h{} (red): {} in {} plies, {:.1} plies deep on average at {:.0} nodes/s",
+        game.black,
+        game.red,
+        record.get_result().as_str(),
+        plies,
+        depths as f64 / plies.max(1) as f64,
+        nodes as f64 / search_time.max(1e-3)
     );
+    record
+}
 
-    return match is_game_over.unwrap_or_else(|| {
-        red_counter = std::u32::MAX;
-        black_counter = std::u32::MAX;
-        let (my_pieces, other_pieces) = b.get_pieces();
-        if my_pieces.len() > other_pieces.len() {
-            return b.get_current_player();
+// plays the games on `threads` threads, the records come back in the order of the games
+fn play_games(
+    population: &[Heuristic],
+    games: &[Game],
+    args: &Args,
+    generation: u32,
+) -> Vec<PdnGame> {
+    let next = AtomicUsize::new(0);
+    let records = Mutex::new(vec![None; games.len()]);
+    thread::scope(|scope| {
+        for _ in 0..args.threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(game) = games.get(i) else {
+                    return;
+                };
+                let record = play_game(population, game, args.move_time, generation);
+                records.lock().expect("Poisoned lock")[i] = Some(record);
+            });
         }
-        return b.get_current_player().get_other();
-    }) {
-        Player::Red => (red_counter, red_h),
-        Player::Black => (black_counter, black_h),
-    };
+    });
+    records
+        .into_inner()
+        .expect("Poisoned lock")
+        .into_iter()
+        .map(|record| record.expect("Err: a game was not played"))
+        .collect()
 }
 
-fn run_generation(prev: Heuristic, siblings: u32, generation: u32) -> GameResult {
-    println!("Initalizing generation: {}\n h(n) = {:?}", generation, prev);
-    let time_to_beat_base = Arc::new(RwLock::new(std::u32::MAX));
-    let mut children = vec![];
-    for i in 0..siblings {
-        let base = prev.clone();
-        let time_to_beat = Arc::clone(&time_to_beat_base);
-        children.push(thread::spawn(move || {
-            let black_h = base.mutate();
-            let red_h = base.clone();
-            println!("Starting game {}", i);
-            let res = game_loop(red_h, black_h, i, &time_to_beat);
-            {
-                let mut t = time_to_beat.write().expect("Posined Lock");
-                if *t > res.0 {
-                    *t = res.0;
+// every pairing plays `games` games with each colour
+fn round_robin(population: usize, games: usize, rng: &mut StdRng) -> Vec<Game> {
+    let mut round = Vec::new();
+    for a in 0..population {
+        for b in a + 1..population {
+            for _ in 0..games {
+                for (black, red) in [(a, b), (b, a)] {
+                    round.push(Game {
+                        black,
+                        red,
+                        seed: rng.gen(),
+                    });
                 }
             }
-            return res;
-        }));
+        }
+    }
+    round
+}
+
+// 2 points for a win and 1 for a draw, as a share of the points the individual could have won
+fn fitness(population: usize, games: &[Game], records: &[PdnGame]) -> Vec<f64> {
+    let mut points = vec![0; population];
+    let mut played = vec![0; population];
+    for (game, record) in games.iter().zip(records) {
+        let (black, red) = match record.get_result() {
+            PdnResult::BlackWin => (2, 0),
+            PdnResult::RedWin => (0, 2),
+            PdnResult::Draw | PdnResult::Unfinished => (1, 1),
+        };
+        points[game.black] += black;
+        points[game.red] += red;
+        played[game.black] += 1;
+        played[game.red] += 1;
     }
+    points
+        .iter()
+        .zip(played)
+        .map(|(&p, n)| p as f64 / (2 * n).max(1) as f64)
+        .collect()
+}
 
-    let mut best_result: GameResult = (std::u32::MAX, prev);
+// the fittest of a few individuals picked at random
+fn tournament<'a>(ranked: &'a [(f64, Heuristic)], rng: &mut StdRng) -> &'a Heuristic {
+    let best = (0..TOURNAMENT_SIZE)
+        .map(|_| rng.gen_range(0..ranked.len()))
+        .min()
+        .expect("Err: empty tournament");
+    // ranked is sorted best first, so the smallest index wins
+    &ranked[best].1
+}
 
-    for child in children {
-        let g_result = child.join().expect("Thread Crashed");
-        if g_result.0 < best_result.0 {
-            best_result = g_result
-        }
+fn next_generation(ranked: &[(f64, Heuristic)], args: &Args, rng: &mut StdRng) -> Vec<Heuristic> {
+    let mut next: Vec<Heuristic> = ranked
+        .iter()
+        .take(args.elite)
+        .map(|(_, h)| h.clone())
+        .collect();
+    while next.len() < args.population {
+        let a = tournament(ranked, rng);
+        let b = tournament(ranked, rng);
+        next.push(a.crossover(b, rng).mutate(rng));
+    }
+    next
+}
+
+fn save_games(games: &[PdnGame]) {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(GAMES_FILE)
+        .expect("FS Error");
+    for game in games {
+        writeln!(f, "{}", game).expect("Error Writting");
     }
+}
 
-    return best_result;
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T> {
+    value
+        .parse::<T>()
+        .map_err(|_| anyhow!("{} needs a number, not {}", flag, value))
+}
+
+// Training starts from the default weights or the weight file given with --weights, the first
+// generation is it and mutations of it. With --seed the mutations and pairings are the same in
+// every run, the games still depend on how far each search gets in its time.
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        population: 8,
+        generations: 10,
+        games: 1,
+        elite: 2,
+        move_time: Duration::from_millis(200),
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        start: Heuristic::default_new(),
+        seed: None,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        let value = argv
+            .next()
+            .ok_or_else(|| anyhow!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--population" => args.population = parse_number(&arg, &value)?,
+            "--generations" => args.generations = parse_number(&arg, &value)?,
+            "--games" => args.games = parse_number(&arg, &value)?,
+            "--elite" => args.elite = parse_number(&arg, &value)?,
+            "--time" => args.move_time = Duration::from_millis(parse_number(&arg, &value)?),
+            "--threads" => args.threads = parse_number(&arg, &value)?,
+            "--weights" => {
+                args.start = Heuristic::load(&value).context("Could not load the weights")?
+            }
+            "--seed" => args.seed = Some(parse_number(&arg, &value)?),
+            _ => bail!("Unexpected argument {}", arg),
+        }
+    }
+    if args.population < 2 {
+        bail!("--population needs at least 2 individuals to play each other");
+    }
+    if args.games == 0 || args.threads == 0 {
+        bail!("--games and --threads need to be at least 1");
+    }
+    if args.elite > args.population {
+        bail!("--elite can't be larger than the population");
+    }
+    Ok(args)
 }
 
 fn main() {
-    let mut h = Heuristic::default_new();
-    for i in 0..10 {
-        let (c, nh) = run_generation(h, 50, i);
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("Error: {:#}\n{}", e, USAGE);
+            std::process::exit(1);
+        }
+    };
+    let mut rng = new_rng(args.seed);
+    let mut population = vec![args.start.clone()];
+    while population.len() < args.population {
+        population.push(args.start.mutate(&mut rng));
+    }
+    for generation in 0..args.generations {
+        let games = round_robin(population.len(), args.games, &mut rng);
         println!(
-            "Generation {} ended selected new h: {:?} with c {}",
-            i, nh, c
+            "Generation {}: {} individuals play {} games",
+            generation,
+            population.len(),
+            games.len()
         );
-        h = nh;
+        let records = play_games(&population, &games, &args, generation);
+        save_games(&records);
+
+        let mut ranked: Vec<(f64, Heuristic)> = fitness(population.len(), &games, &records)
+            .into_iter()
+            .zip(population)
+            .collect();
+        // stable, so equal fitness keeps the elite of the last generation in front
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        println!("Generation {} standings:", generation);
+        for (i, (fitness, h)) in ranked.iter().enumerate() {
+            println!("{:>3}. {:>5.1}% {:?}", i + 1, 100.0 * fitness, h);
+        }
+        if let Err(e) = ranked[0].1.save(WEIGHTS_FILE) {
+            println!("Error: {:#}", e);
+        }
+        population = next_generation(&ranked, &args, &mut rng);
     }
 }
@@ -0,0 +1,115 @@
+// Texel tuning of the heuristic weights on the positions of PDN games, for example the ones the
+// trainer writes to train_games.pdn. See ai/tuning.rs.
+use anyhow::{anyhow, bail, Context, Result};
+use checkers::ai::heuristic::{Heuristic, WEIGHT_NAMES};
+use checkers::ai::tuning::{error, fit_scale, positions_from_games, tune};
+use checkers::board::pdn::parse_pdn;
+use std::fs::read_to_string;
+use std::time::Instant;
+
+const USAGE: &str =
+    "Usage: tune <pdn file>... [--weights <path>] [--out <path>] [--skip <plies>] [--passes <n>]";
+const DEFAULT_PATH: &str = "tuned_weights.json";
+
+struct Args {
+    files: Vec<String>,
+    start: Heuristic,
+    out: String,
+    // opening plies of every game that are left out
+    skip: usize,
+    passes: u32,
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T> {
+    let value = value.ok_or_else(|| anyhow!("{} needs a value", flag))?;
+    value
+        .parse::<T>()
+        .map_err(|_| anyhow!("{} needs a number, not {}", flag, value))
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        files: Vec::new(),
+        start: Heuristic::default_new(),
+        out: String::from(DEFAULT_PATH),
+        skip: 8,
+        passes: 100,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--weights" => {
+                let path = argv
+                    .next()
+                    .ok_or_else(|| anyhow!("--weights needs a path"))?;
+                args.start = Heuristic::load(&path)?;
+            }
+            "--out" => args.out = argv.next().ok_or_else(|| anyhow!("--out needs a path"))?,
+            "--skip" => args.skip = parse_number(&arg, argv.next())?,
+            "--passes" => args.passes = parse_number(&arg, argv.next())?,
+            _ if !arg.starts_with("--") => args.files.push(arg),
+            _ => bail!("Unexpected argument {}", arg),
+        }
+    }
+    if args.files.is_empty() {
+        bail!("Missing the PDN files");
+    }
+    Ok(args)
+}
+
+fn run(args: &Args) -> Result<()> {
+    let mut games = Vec::new();
+    for file in &args.files {
+        let text = read_to_string(file).with_context(|| format!("Could not read {}", file))?;
+        games.extend(parse_pdn(&text).with_context(|| format!("In {}", file))?);
+    }
+    let positions = positions_from_games(&games, args.skip)?;
+    if positions.is_empty() {
+        bail!("The games have no finished positions to tune on");
+    }
+    println!(
+        "Tuning on {} positions from {} games",
+        positions.len(),
+        games.len()
+    );
+
+    let now = Instant::now();
+    let scale = fit_scale(&args.start, &positions);
+    let before = error(&args.start, &positions, scale);
+    println!("Scale {:.3e}, error before tuning {:.6}", scale, before);
+    let tuned = tune(&args.start, &positions, scale, args.passes);
+    let after = error(&tuned, &positions, scale);
+    println!(
+        "Error after tuning {:.6} ({:+.6}) in {:.1}s",
+        after,
+        after - before,
+        now.elapsed().as_secs_f64()
+    );
+
+    println!("{:<22}{:>10}{:>10}", "weight", "before", "after");
+    let weights = args
+        .start
+        .get_weights()
+        .into_iter()
+        .zip(tuned.get_weights());
+    for (name, (before, after)) in WEIGHT_NAMES.iter().zip(weights) {
+        println!("{:<22}{:>10}{:>10}", name, before, after);
+    }
+    tuned.save(&args.out)?;
+    println!("Saved the tuned weights to {}", args.out);
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("Error: {:#}\n{}", e, USAGE);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = run(&args) {
+        println!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
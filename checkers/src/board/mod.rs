@@ -1,9 +1,13 @@
+pub mod pdn;
+mod perft;
+mod zobrist;
 use anyhow::{anyhow, bail, Context, Result};
 use colored::{ColoredString, Colorize};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, str::FromStr};
+use std::str::FromStr;
 
 pub type Cord = (usize, usize);
+pub type Piece = (BoardPiece, Cord);
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BoardPiece {
@@ -66,21 +70,11 @@ impl BoardPiece {
     }
 
     pub fn is_king(&self) -> bool {
-        *self as u32 % 2 != 0 && *self != BoardPiece::Empty
-    }
-
-    fn promote(&self) -> Self {
-        if self.is_red() {
-            Self::KingRed
-        } else if self.is_black() {
-            Self::KingBlack
-        } else {
-            Self::Empty
-        }
+        !(*self as u32).is_multiple_of(2) && *self != BoardPiece::Empty
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Player {
     Black = 1,
     Red = -1,
@@ -103,44 +97,136 @@ impl Player {
         }
     }
 
-    fn does_piece_match(&self, piece: BoardPiece) -> bool {
-        match *self {
-            Self::Black => piece.is_black(),
-            Self::Red => piece.is_red(),
+    // the row a man of this player is promoted on
+    fn promotion_row(&self) -> u32 {
+        match self {
+            Self::Black => ROW_7,
+            Self::Red => ROW_0,
+        }
+    }
+
+    fn forward(&self) -> [Direction; 2] {
+        match self {
+            Self::Black => [Direction::NorthEast, Direction::NorthWest],
+            Self::Red => [Direction::SouthEast, Direction::SouthWest],
         }
     }
 }
 
+// The 32 playable squares are stored one per bit. Bit `i` is square `i + 1`
+// in the standard checkers numbering: black starts on bits 0..12 (rows 0-2)
+// and red on bits 20..32 (rows 5-7). Each row holds 4 squares, on even rows
+// they sit in columns 6, 4, 2, 0 and on odd rows in columns 7, 5, 3, 1.
+const EVEN_ROWS: u32 = 0x0F0F_0F0F;
+const ODD_ROWS: u32 = 0xF0F0_F0F0;
+// first / last square of every row, needed to stop shifts wrapping around
+const FIRST_IN_ROW: u32 = 0x1111_1111;
+const LAST_IN_ROW: u32 = 0x8888_8888;
+const ROW_0: u32 = 0x0000_000F;
+const ROW_7: u32 = 0xF000_0000;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum Move {
-    ForwardRight,
-    ForwardLeft,
-    Jump,
-    BackwardRight = 10,
-    BackwardLeft = 11,
+enum Direction {
+    // north is towards row 7 (blacks forward), east is towards column 7
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+const ALL_DIRECTIONS: [Direction; 4] = [
+    Direction::NorthEast,
+    Direction::NorthWest,
+    Direction::SouthEast,
+    Direction::SouthWest,
+];
+
+impl Direction {
+    // moves every square in the bitboard one diagonal step, dropping anything that falls off
+    fn shift(&self, bb: u32) -> u32 {
+        match self {
+            Self::NorthEast => ((bb & EVEN_ROWS) << 4) | ((bb & ODD_ROWS & !FIRST_IN_ROW) << 3),
+            Self::NorthWest => ((bb & EVEN_ROWS & !LAST_IN_ROW) << 5) | ((bb & ODD_ROWS) << 4),
+            Self::SouthEast => ((bb & EVEN_ROWS) >> 4) | ((bb & ODD_ROWS & !FIRST_IN_ROW) >> 5),
+            Self::SouthWest => ((bb & EVEN_ROWS & !LAST_IN_ROW) >> 3) | ((bb & ODD_ROWS) >> 4),
+        }
+    }
+
+    fn opposite(&self) -> Self {
+        match self {
+            Self::NorthEast => Self::SouthWest,
+            Self::NorthWest => Self::SouthEast,
+            Self::SouthEast => Self::NorthWest,
+            Self::SouthWest => Self::NorthEast,
+        }
+    }
+}
+
+// iterates over the square index of every set bit
+struct Squares(u32);
+
+impl Iterator for Squares {
+    type Item = usize;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+        let sq = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(sq)
+    }
+}
+
+fn square_to_cord(sq: usize) -> Cord {
+    let row = sq / 4;
+    let col = 6 - 2 * (sq % 4) + row % 2;
+    (row, col)
+}
+
+fn cord_to_square((row, col): Cord) -> usize {
+    row * 4 + (6 + row % 2 - col) / 2
+}
+
+fn cord_to_bit(cord: Cord) -> u32 {
+    1 << cord_to_square(cord)
+}
+
+// the standard 1-32 square number of a playable square, black starts on 1-12 and red on 21-32
+pub fn square_number(cord: Cord) -> usize {
+    cord_to_square(cord) + 1
 }
 
-impl Move {
-    fn must_be_king(&self) -> bool {
-        *self as u32 > 9
+pub fn square_from_number(number: usize) -> Option<Cord> {
+    match number {
+        1..=32 => Some(square_to_cord(number - 1)),
+        _ => None,
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Moves {
-    jump_path: HashSet<Cord>,
+    // the pieces jumped over, in the order they are jumped
+    jump_path: Vec<Cord>,
+    // the square landed on after each jump, the last one is end_loc
+    landings: Vec<Cord>,
     start_loc: Cord,
     end_loc: Cord,
 }
 impl std::fmt::Display for Moves {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         let (start_row, start_col) = self.start_loc;
-        let (end_row, end_col) = self.end_loc;
-        write!(
-            fmt,
-            "start: {},{} -> end: {},{} jumps: {:?}",
-            start_row, start_col, end_row, end_col, self.jump_path
-        )
+        write!(fmt, "start: {},{}", start_row, start_col)?;
+        let path = match self.is_jump() {
+            true => &self.landings[..],
+            false => std::slice::from_ref(&self.end_loc),
+        };
+        for (row, col) in path.iter() {
+            write!(fmt, " -> {},{}", row, col)?;
+        }
+        if self.is_jump() {
+            write!(fmt, " jumps: {:?}", self.jump_path)?;
+        }
+        Ok(())
     }
 }
 
@@ -149,20 +235,68 @@ impl Moves {
         Moves {
             start_loc: (9, 9),
             end_loc: (9, 9),
-            jump_path: HashSet::new(),
+            jump_path: Vec::new(),
+            landings: Vec::new(),
+        }
+    }
+
+    fn new_step(start_loc: Cord, end_loc: Cord) -> Self {
+        Moves {
+            start_loc,
+            end_loc,
+            jump_path: Vec::new(),
+            landings: Vec::new(),
         }
     }
 
     pub fn is_jump(&self) -> bool {
         !self.jump_path.is_empty()
     }
+
+    pub fn get_start_loc(&self) -> Cord {
+        self.start_loc
+    }
+
+    pub fn get_end_loc(&self) -> Cord {
+        self.end_loc
+    }
+
+    pub fn get_jump_path(&self) -> &[Cord] {
+        &self.jump_path
+    }
+
+    pub fn get_landings(&self) -> &[Cord] {
+        &self.landings
+    }
+
+    // standard notation using the 1-32 square numbers, "11-15" for a move and "22x15x8" for a
+    // jump with every square it lands on
+    pub fn notation(&self) -> String {
+        if !self.is_jump() {
+            return format!(
+                "{}-{}",
+                square_number(self.start_loc),
+                square_number(self.end_loc)
+            );
+        }
+        std::iter::once(&self.start_loc)
+            .chain(self.landings.iter())
+            .map(|&cord| square_number(cord).to_string())
+            .collect::<Vec<_>>()
+            .join("x")
+    }
+
+    fn captured(&self) -> u32 {
+        self.jump_path
+            .iter()
+            .fold(0, |acc, &cord| acc | cord_to_bit(cord))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PlayerInfo {
     moves: Vec<Moves>,
     can_jump: bool,
-    piece_locs: HashSet<Cord>,
     player: Player,
 }
 
@@ -173,120 +307,91 @@ impl std::fmt::Display for PlayerInfo {
             .iter()
             .enumerate()
             .fold(String::from(""), |acc, (i, mv)| {
-                format!("{}{}. {}\n", acc, i, mv)
+                format!("{}{}. {} ({})\n", acc, i, mv.notation(), mv)
             });
         write!(fmt, "{}", printable)
     }
 }
 
 impl PlayerInfo {
+    fn new(player: Player) -> Self {
+        Self {
+            moves: Vec::new(),
+            can_jump: false,
+            player,
+        }
+    }
+
     pub fn get_moves(&self) -> &Vec<Moves> {
         &self.moves
     }
 
     pub fn get_can_jump(&self) -> bool {
-        return self.can_jump;
+        self.can_jump
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Players {
-    players: [PlayerInfo; 2],
-    black: usize,
-    red: usize,
-}
-impl Default for Players {
-    fn default() -> Self {
-        Self {
-            players: [
-                PlayerInfo {
-                    moves: Vec::new(),
-                    can_jump: false,
-                    piece_locs: HashSet::with_capacity(12),
-                    player: Player::Black,
-                },
-                PlayerInfo {
-                    moves: Vec::new(),
-                    can_jump: false,
-                    piece_locs: HashSet::with_capacity(12),
-                    player: Player::Red,
-                },
-            ],
-            black: 0,
-            red: 1,
-        }
-    }
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DrawReason {
+    // the same position with the same player to move came up three times
+    Repetition,
+    // neither player captured or moved a man for the draw move limit
+    NoProgress,
 }
-impl Players {
-    fn swap(&mut self) {
-        self.players.rotate_left(1);
-        self.black = 1 - self.black;
-        self.red = 1 - self.red;
-    }
 
-    fn get_current_players_mut(&mut self) -> (&mut PlayerInfo, &mut PlayerInfo) {
-        let (a, b) = self.players.split_at_mut(1);
-        (&mut a[0], &mut b[0])
-    }
-    fn get_current_players(&self) -> (&PlayerInfo, &PlayerInfo) {
-        (&self.players[0], &self.players[1])
-    }
-
-    fn get_current_player(&self) -> &PlayerInfo {
-        &self.players[0]
-    }
-    fn get_current_player_mut(&mut self) -> &mut PlayerInfo {
-        &mut self.players[0]
-    }
-    #[allow(dead_code)]
-    fn get_other_player(&self) -> &PlayerInfo {
-        &self.players[1]
-    }
-    #[allow(dead_code)]
-    fn get_other_player_mut(&mut self) -> &mut PlayerInfo {
-        &mut self.players[1]
-    }
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameOutcome {
+    Win(Player),
+    Draw(DrawReason),
+}
 
-    #[allow(dead_code)]
-    fn get_red(&self) -> &PlayerInfo {
-        &self.players[self.red]
-    }
-    #[allow(dead_code)]
-    fn get_red_mut(&mut self) -> &mut PlayerInfo {
-        &mut self.players[self.red]
-    }
+// moves per player without a capture or a man moving before the game is a draw
+pub const DEFAULT_DRAW_MOVE_LIMIT: u32 = 40;
 
-    #[allow(dead_code)]
-    fn get_black(&self) -> &PlayerInfo {
-        &self.players[self.black]
-    }
-    #[allow(dead_code)]
-    fn get_black_mut(&mut self) -> &mut PlayerInfo {
-        &mut self.players[self.black]
-    }
+// everything make_move changes, so unmake_move can put it back exactly
+#[derive(Debug, Clone)]
+pub struct UndoInfo {
+    black: u32,
+    red: u32,
+    kings: u32,
+    hash: u64,
+    reversible_plies: u32,
+    // the moves of the player who made the move, so they don't have to be generated again
+    moves: Vec<Moves>,
+    can_jump: bool,
 }
 
 const BOARD_SIZE: usize = 8;
 #[derive(Debug, Clone)]
 pub struct Board {
-    board: [[BoardPiece; BOARD_SIZE]; BOARD_SIZE],
-    players: Players, // black_info: Rc<RefCell<PlayerInfo>>,
-                      // red_info: Rc<RefCell<PlayerInfo>>,
-                      // current_player: Rc<RefCell<PlayerInfo>>,
+    black: u32,
+    red: u32,
+    kings: u32,
+    // zobrist hash of the pieces and the player to move, kept up to date by every move
+    hash: u64,
+    // hashes of every position before this one, used to find repetitions
+    history: Vec<u64>,
+    // plies since the last capture or man move, only positions in that window can repeat
+    reversible_plies: u32,
+    draw_move_limit: u32,
+    // moves are only ever calculated for the player whose turn it is
+    player_info: PlayerInfo,
+    // move lists given back by unmake_move, reused so make_move doesn't allocate
+    spare_moves: Vec<Vec<Moves>>,
 }
 
 pub struct OutputFileBoard<'a>(&'a Board);
 
 impl<'a> std::fmt::Display for OutputFileBoard<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for row in self.0.board.iter().rev() {
+        for row in self.0.to_array().iter().rev() {
             for col in row.iter() {
                 let char: char = (*col).into();
                 write!(f, "{} ", char)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
-        let player: u8 = self.0.players.get_current_player().player.into();
+        let player: u8 = self.0.player_info.player.into();
         write!(f, "{}", player)
     }
 }
@@ -296,7 +401,7 @@ impl std::fmt::Display for Board {
         write!(
             fmt,
             "    0  1  2  3  4  5  6  7\n{}",
-            self.board
+            self.to_array()
                 .iter()
                 .enumerate()
                 .fold(String::from(""), |acc, (row_count, row)| {
@@ -326,11 +431,11 @@ impl FromStr for Board {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (board, players) = s.split('\n').enumerate().try_fold(
-            ([[BoardPiece::Empty; 8]; 8], Players::default()),
-            |(mut board, mut players), (i, row)| {
+        let (board, player) = s.split('\n').enumerate().try_fold(
+            ([[BoardPiece::Empty; 8]; 8], Player::Black),
+            |(mut board, mut player), (i, row)| {
                 if i > 8 {
-                    return Ok((board, players));
+                    return Ok((board, player));
                 }
                 if i > 7 {
                     match row
@@ -338,17 +443,17 @@ impl FromStr for Board {
                         .context("Invalid File input: Player is not a number")?
                     {
                         1 => {}
-                        2 => players.swap(),
+                        2 => player = Player::Red,
                         _ => {
                             bail!(
                                 "Invalid File input: Player # must be 1 or 0, defaulting to Black"
                             );
                         }
                     };
-                    return Ok((board, players));
+                    return Ok((board, player));
                 }
                 let mut col_i = ((i % 2) == 0) as usize;
-                for c in row.chars().into_iter() {
+                for c in row.chars() {
                     if c == ' ' || c == '0' {
                         continue;
                     }
@@ -362,488 +467,433 @@ impl FromStr for Board {
                     board[7 - i][col_i] = c.try_into()?;
                     col_i += 2;
                 }
-                Ok((board, players))
+                Ok((board, player))
             },
         )?;
 
-        Ok(Board::new(board, players))
+        Ok(Board::from_array(board, player))
     }
 }
 
 impl Default for Board {
     fn default() -> Self {
-        let board = [
-            [
-                BoardPiece::Black,
-                BoardPiece::Empty,
-                BoardPiece::Black,
-                BoardPiece::Empty,
-                BoardPiece::Black,
-                BoardPiece::Empty,
-                BoardPiece::Black,
-                BoardPiece::Empty,
-            ],
-            [
-                BoardPiece::Empty,
-                BoardPiece::Black,
-                BoardPiece::Empty,
-                BoardPiece::Black,
-                BoardPiece::Empty,
-                BoardPiece::Black,
-                BoardPiece::Empty,
-                BoardPiece::Black,
-            ],
-            [
-                BoardPiece::Black,
-                BoardPiece::Empty,
-                BoardPiece::Black,
-                BoardPiece::Empty,
-                BoardPiece::Black,
-                BoardPiece::Empty,
-                BoardPiece::Black,
-                BoardPiece::Empty,
-            ],
-            [
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-            ],
-            [
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-                BoardPiece::Empty,
-            ],
-            [
-                BoardPiece::Empty,
-                BoardPiece::Red,
-                BoardPiece::Empty,
-                BoardPiece::Red,
-                BoardPiece::Empty,
-                BoardPiece::Red,
-                BoardPiece::Empty,
-                BoardPiece::Red,
-            ],
-            [
-                BoardPiece::Red,
-                BoardPiece::Empty,
-                BoardPiece::Red,
-                BoardPiece::Empty,
-                BoardPiece::Red,
-                BoardPiece::Empty,
-                BoardPiece::Red,
-                BoardPiece::Empty,
-            ],
-            [
-                BoardPiece::Empty,
-                BoardPiece::Red,
-                BoardPiece::Empty,
-                BoardPiece::Red,
-                BoardPiece::Empty,
-                BoardPiece::Red,
-                BoardPiece::Empty,
-                BoardPiece::Red,
-            ],
-        ];
-        Self::new(board, Players::default())
+        Self::new(0x0000_0FFF, 0xFFF0_0000, 0, Player::Black)
     }
 }
 
 impl Board {
-    pub fn display_file<'a>(&'a self) -> OutputFileBoard<'a> {
+    pub fn display_file(&self) -> OutputFileBoard<'_> {
         OutputFileBoard(self)
     }
-    fn new(board: [[BoardPiece; 8]; 8], players: Players) -> Self {
-        let mut obj = Self { board, players };
-        for (row, row_arr) in obj.board.iter().enumerate() {
+
+    fn new(black: u32, red: u32, kings: u32, player: Player) -> Self {
+        let mut obj = Self {
+            black,
+            red,
+            kings,
+            hash: 0,
+            history: Vec::new(),
+            reversible_plies: 0,
+            draw_move_limit: DEFAULT_DRAW_MOVE_LIMIT,
+            player_info: PlayerInfo::new(player),
+            spare_moves: Vec::new(),
+        };
+        obj.hash = obj.calc_hash();
+        obj.calc_moves();
+        obj
+    }
+
+    fn from_array(board: [[BoardPiece; BOARD_SIZE]; BOARD_SIZE], player: Player) -> Self {
+        let (mut black, mut red, mut kings) = (0, 0, 0);
+        for (row, row_arr) in board.iter().enumerate() {
             for (col, el) in row_arr.iter().enumerate() {
+                if *el == BoardPiece::Empty {
+                    continue;
+                }
+                let bit = cord_to_bit((row, col));
                 if el.is_red() {
-                    obj.players.get_red_mut().piece_locs.insert((row, col));
-                } else if el.is_black() {
-                    obj.players.get_black_mut().piece_locs.insert((row, col));
+                    red |= bit;
+                } else {
+                    black |= bit;
+                }
+                if el.is_king() {
+                    kings |= bit;
                 }
             }
         }
+        Self::new(black, red, kings, player)
+    }
 
-        obj.calc_moves();
-        obj
+    fn to_array(&self) -> [[BoardPiece; BOARD_SIZE]; BOARD_SIZE] {
+        let mut board = [[BoardPiece::Empty; BOARD_SIZE]; BOARD_SIZE];
+        for sq in Squares(self.black | self.red) {
+            let (row, col) = square_to_cord(sq);
+            board[row][col] = self.piece_at(sq);
+        }
+        board
     }
 
-    pub fn swap_current_player(&mut self) {
-        self.players.swap();
-        self.calc_moves();
+    fn piece_at(&self, sq: usize) -> BoardPiece {
+        let bit = 1 << sq;
+        let is_king = self.kings & bit != 0;
+        if self.black & bit != 0 {
+            match is_king {
+                true => BoardPiece::KingBlack,
+                false => BoardPiece::Black,
+            }
+        } else if self.red & bit != 0 {
+            match is_king {
+                true => BoardPiece::KingRed,
+                false => BoardPiece::Red,
+            }
+        } else {
+            BoardPiece::Empty
+        }
     }
 
-    pub fn get_pieces(&self) -> (Vec<(BoardPiece, Cord)>, Vec<(BoardPiece, Cord)>) {
-        let (cp, op) = self.players.get_current_players();
+    fn pieces_of(&self, player: Player) -> u32 {
+        match player {
+            Player::Black => self.black,
+            Player::Red => self.red,
+        }
+    }
 
-        let mine = cp
-            .piece_locs
-            .iter()
-            .map(|&(row, col)| (self.board[row][col], (row, col)))
-            .collect();
+    fn pieces_of_mut(&mut self, player: Player) -> &mut u32 {
+        match player {
+            Player::Black => &mut self.black,
+            Player::Red => &mut self.red,
+        }
+    }
 
-        let other = op
-            .piece_locs
-            .iter()
-            .map(|&(row, col)| (self.board[row][col], (row, col)))
-            .collect();
+    fn empty(&self) -> u32 {
+        !(self.black | self.red)
+    }
 
-        return (mine, other);
+    fn calc_hash(&self) -> u64 {
+        let pieces = Squares(self.black | self.red).fold(0, |acc, sq| acc ^ self.piece_key(sq));
+        match self.player_info.player {
+            Player::Black => pieces,
+            Player::Red => pieces ^ zobrist::RED_TO_MOVE,
+        }
     }
 
-    pub fn get_player_info(&self) -> &PlayerInfo {
-        &self.players.get_current_player()
+    fn piece_key(&self, sq: usize) -> u64 {
+        zobrist::PIECE_KEYS[self.piece_at(sq) as usize][sq]
     }
 
-    pub fn print_moves(&self) {
-        let player = self.players.get_current_player();
-        println!("Player: {:?}\n{}", player.player, player);
-    }
-
-    fn calc_jumps(&mut self, row: usize, col: usize) {
-        self.dfs_jumps(
-            row,
-            col,
-            Moves {
-                start_loc: (row, col),
-                end_loc: (9, 9),
-                jump_path: HashSet::new(),
-            },
-        );
+    pub fn get_hash(&self) -> u64 {
+        self.hash
     }
 
-    fn can_jump(
-        &self,
-        enemy_row: i32,
-        enemy_col: i32,
-        new_row: i32,
-        new_col: i32,
-        path_par: &Moves,
-        player: Player,
-    ) -> bool {
-        !self.is_off_screen(enemy_row, enemy_col)
-            && !self.is_off_screen(new_row, new_col)
-            && !path_par
-                .jump_path
-                .contains(&(enemy_row as usize, enemy_col as usize))
-            && player
-                .get_other()
-                .does_piece_match(self.board[enemy_row as usize][enemy_col as usize])
-            && (self.board[new_row as usize][new_col as usize] == BoardPiece::Empty
-                || path_par
-                    .jump_path
-                    .contains(&(new_row as usize, new_col as usize))
-                || (path_par.start_loc.0 as i32 == new_row
-                    && path_par.start_loc.1 as i32 == new_col))
-    }
-
-    fn dfs_jumps(&mut self, row: usize, col: usize, path_par: Moves) {
-        let player = self.players.get_current_player().player;
-        // let p_info = &mut self.players.get_current_player_mut().moves;
-        let mut nothing_found = true;
+    // the black, red and king bitboards, bit i is square i + 1
+    pub fn get_bitboards(&self) -> (u32, u32, u32) {
+        (self.black, self.red, self.kings)
+    }
 
-        // check right ;
-        if self.can_jump(
-            row as i32 + player as i32,
-            col as i32 + 1,
-            row as i32 + 2 * (player as i32),
-            col as i32 + 2,
-            &path_par,
-            player,
-        ) {
-            nothing_found = false;
-            let mut path = path_par.clone();
-            path.jump_path
-                .insert(((row as i32 + player as i32) as usize, col + 1));
-            self.dfs_jumps((row as i32 + 2 * (player as i32)) as usize, col + 2, path);
+    // a position from bitboards laid out like get_bitboards, without any history
+    pub fn from_bitboards(black: u32, red: u32, kings: u32, player: Player) -> Result<Self> {
+        if black & red != 0 {
+            bail!("Invalid bitboards: a square holds both a black and a red piece");
         }
-
-        // check left
-        if self.can_jump(
-            row as i32 + player as i32,
-            col as i32 - 1,
-            row as i32 + 2 * (player as i32),
-            col as i32 - 2,
-            &path_par,
-            player,
-        ) {
-            nothing_found = false;
-            let mut path = path_par.clone();
-            path.jump_path
-                .insert(((row as i32 + player as i32) as usize, col - 1));
-            self.dfs_jumps((row as i32 + 2 * (player as i32)) as usize, col - 2, path);
-        }
-        // /check back right
-        if self.board[path_par.start_loc.0][path_par.start_loc.1].is_king()
-            && self.can_jump(
-                row as i32 - player as i32,
-                col as i32 + 1,
-                row as i32 - 2 * (player as i32),
-                col as i32 + 2,
-                &path_par,
-                player,
-            )
-        {
-            nothing_found = false;
-            let mut path = path_par.clone();
-            path.jump_path
-                .insert(((row as i32 - player as i32) as usize, col + 1));
-            self.dfs_jumps((row as i32 - 2 * (player as i32)) as usize, col + 2, path);
-        }
-
-        // check back left
-        if self.board[path_par.start_loc.0][path_par.start_loc.1].is_king()
-            && self.can_jump(
-                row as i32 - player as i32,
-                col as i32 - 1,
-                row as i32 - 2 * (player as i32),
-                col as i32 - 2,
-                &path_par,
-                player,
-            )
-        {
-            nothing_found = false;
-            let mut path = path_par.clone();
-            path.jump_path
-                .insert(((row as i32 - player as i32) as usize, col - 1));
-            self.dfs_jumps((row as i32 - 2 * (player as i32)) as usize, col - 2, path);
+        if kings & !(black | red) != 0 {
+            bail!("Invalid bitboards: a king on an empty square");
         }
-        if nothing_found {
-            let p_info = &mut self.players.get_current_player_mut().moves;
-            p_info.push(path_par);
-            p_info.last_mut().unwrap().end_loc = (row, col);
+        if black.count_ones() > 12 || red.count_ones() > 12 {
+            bail!("Invalid bitboards: a side has more than 12 pieces");
         }
+        let men = !kings;
+        if (black & men & ROW_7) | (red & men & ROW_0) != 0 {
+            bail!("Invalid bitboards: a man on the row it should have been crowned on");
+        }
+        Ok(Self::new(black, red, kings, player))
     }
 
-    fn calc_moves(&mut self) {
-        // let mut p_info = &mut *self.current_player.borrow_mut();
+    pub fn swap_current_player(&mut self) {
+        self.player_info.player = self.player_info.player.get_other();
+        self.hash ^= zobrist::RED_TO_MOVE;
+        self.calc_moves();
+    }
 
-        let rows = self
-            .players
-            .get_current_player()
-            .piece_locs
-            .iter()
-            .cloned()
-            .collect::<Box<_>>();
+    pub fn get_pieces(&self) -> (Vec<Piece>, Vec<Piece>) {
+        let player = self.player_info.player;
+        let collect = |bb: u32| {
+            Squares(bb)
+                .map(|sq| (self.piece_at(sq), square_to_cord(sq)))
+                .collect()
+        };
 
-        {
-            let p_info = self.players.get_current_player_mut();
-            p_info.moves.clear();
-            p_info.can_jump = false;
-        }
+        (
+            collect(self.pieces_of(player)),
+            collect(self.pieces_of(player.get_other())),
+        )
+    }
 
-        for &(row, col) in rows.into_iter() {
-            if self.is_move_legal(row, col, Move::Jump) {
-                if !self.players.get_current_player().can_jump {
-                    self.players.get_current_player_mut().moves.clear();
-                    self.players.get_current_player_mut().can_jump = true;
-                }
-                self.calc_jumps(row, col);
-            }
-            if self.players.get_current_player().can_jump {
-                continue;
-            }
+    pub fn get_player_info(&self) -> &PlayerInfo {
+        &self.player_info
+    }
 
-            if self.is_move_legal(row, col, Move::ForwardRight) {
-                let p_info = self.players.get_current_player_mut();
-                p_info.moves.push(Moves {
-                    start_loc: (row, col),
-                    end_loc: (((row as i32) + (p_info.player as i32)) as usize, col + 1),
-                    jump_path: HashSet::new(),
-                });
-            }
-            if self.is_move_legal(row, col, Move::ForwardLeft) {
-                let p_info = self.players.get_current_player_mut();
-                p_info.moves.push(Moves {
-                    start_loc: (row, col),
-                    end_loc: (((row as i32) + (p_info.player as i32)) as usize, col - 1),
-                    jump_path: HashSet::new(),
-                });
-            }
-            if self.is_move_legal(row, col, Move::BackwardRight) {
-                let p_info = self.players.get_current_player_mut();
-                p_info.moves.push(Moves {
-                    start_loc: (row, col),
-                    end_loc: (((row as i32) - (p_info.player as i32)) as usize, col + 1),
-                    jump_path: HashSet::new(),
-                });
-            }
-            if self.is_move_legal(row, col, Move::BackwardLeft) {
-                let p_info = self.players.get_current_player_mut();
-                p_info.moves.push(Moves {
-                    start_loc: (row, col),
-                    end_loc: (((row as i32) - (p_info.player as i32)) as usize, col - 1),
-                    jump_path: HashSet::new(),
-                });
+    // finds the legal move written as "11-15" or "22x15x8", the squares a jump lands on before
+    // the last one can be left out as long as that doesn't leave more than one move
+    pub fn parse_move(&self, text: &str) -> Result<usize> {
+        let text = text.trim();
+        let squares = text
+            .split(['-', 'x', 'X'])
+            .map(|n| {
+                n.trim()
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(square_from_number)
+                    .ok_or_else(|| anyhow!("Invalid square {:?} in move {}", n, text))
+            })
+            .collect::<Result<Vec<Cord>>>()?;
+        if squares.len() < 2 {
+            bail!(
+                "Invalid move {}, expected something like 11-15 or 22x15",
+                text
+            );
+        }
+        let start = squares[0];
+        let end = squares[squares.len() - 1];
+
+        let moves = &self.player_info.moves;
+        let found: Vec<usize> = (0..moves.len())
+            .filter(|&i| {
+                let mv = &moves[i];
+                mv.start_loc == start
+                    && mv.end_loc == end
+                    && (squares.len() == 2 || mv.landings == squares[1..])
+            })
+            .collect();
+        let legal = || {
+            moves
+                .iter()
+                .map(|mv| mv.notation())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match found[..] {
+            [mv] => Ok(mv),
+            [] if self.player_info.can_jump && !text.contains(['x', 'X']) => {
+                bail!("Illegal move {}, a jump has to be taken: {}", text, legal())
             }
+            [] => bail!("Illegal move {}, the legal moves are: {}", text, legal()),
+            _ => bail!(
+                "Ambiguous move {}, it could be any of: {}",
+                text,
+                found
+                    .iter()
+                    .map(|&i| moves[i].notation())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
         }
     }
 
-    pub fn is_game_over(&self) -> Option<Player> {
-        let p_info = self.players.get_current_player();
-        if p_info.moves.is_empty() {
-            return Option::Some(p_info.player.get_other());
-        }
-        return Option::None;
+    pub fn print_moves(&self) {
+        println!(
+            "Player: {:?}\n{}",
+            self.player_info.player, self.player_info
+        );
     }
 
-    pub fn do_move(&mut self, mv: usize) -> bool {
-        // let mut player_info = self.current_player.borrow_mut();
-        // let mut other_player = match player_info.player {
-        //     Player::Red => self.black_info.borrow_mut(),
-        //     Player::Black => self.red_info.borrow_mut(),
-        // };
-
-        let (player_info, other_player) = self.players.get_current_players_mut();
-        let move_obj = match player_info.moves.get(mv) {
-            Some(m) => m,
-            None => return false,
-        };
-
-        let (start_row, start_col) = move_obj.start_loc;
-        let (end_row, end_col) = move_obj.end_loc;
-
-        if end_row == 7 || end_row == 0 {
-            self.board[end_row][end_col] = self.board[start_row][start_col].promote();
-        } else {
-            self.board[end_row][end_col] = self.board[start_row][start_col];
+    // all the directions the piece on this square is allowed to move in
+    fn directions(&self, sq: usize) -> &'static [Direction] {
+        if self.kings & (1 << sq) != 0 {
+            return &ALL_DIRECTIONS;
+        }
+        match self.player_info.player {
+            Player::Black => &ALL_DIRECTIONS[..2],
+            Player::Red => &ALL_DIRECTIONS[2..],
         }
-        self.board[start_row][start_col] = BoardPiece::Empty;
+    }
 
-        for &(row, col) in move_obj.jump_path.iter() {
-            self.board[row][col] = BoardPiece::Empty;
-            other_player.piece_locs.remove(&(row, col));
+    // the pieces of the current player that are allowed to step in this direction
+    fn movers(&self, dir: &Direction) -> u32 {
+        let player = self.player_info.player;
+        let mine = self.pieces_of(player);
+        match player.forward().contains(dir) {
+            true => mine,
+            false => mine & self.kings,
         }
+    }
 
-        player_info.piece_locs.remove(&(start_row, start_col));
-        player_info.piece_locs.insert((end_row, end_col));
+    // the pieces of the current player that have at least one jump available
+    fn jumpers(&self) -> u32 {
+        let theirs = self.pieces_of(self.player_info.player.get_other());
+        let empty = self.empty();
+        ALL_DIRECTIONS.iter().fold(0, |acc, dir| {
+            let back = dir.opposite();
+            acc | (self.movers(dir) & back.shift(theirs & back.shift(empty)))
+        })
+    }
 
-        // let last_player = player_info.player;
-        // let next_player = other_player.player;
+    fn dfs_jumps(&mut self, sq: usize, start: usize, captured: u32, path: &mut Moves) {
+        let theirs = self.pieces_of(self.player_info.player.get_other());
+        // the jumping piece has left its start square, captured pieces stay until the move ends
+        let empty = self.empty() | (1 << start);
+        let mut nothing_found = true;
 
-        // drop(player_info);
-        // drop(other_player);
+        for dir in self.directions(start) {
+            let over = dir.shift(1 << sq);
+            if over & theirs & !captured == 0 {
+                continue;
+            }
+            let land = dir.shift(over);
+            if land & empty == 0 {
+                continue;
+            }
+            nothing_found = false;
+            let land_sq = land.trailing_zeros() as usize;
+            path.jump_path
+                .push(square_to_cord(over.trailing_zeros() as usize));
+            path.landings.push(square_to_cord(land_sq));
+            self.dfs_jumps(land_sq, start, captured | over, path);
+            path.jump_path.pop();
+            path.landings.pop();
+        }
 
-        self.players.swap();
+        if nothing_found {
+            let mut mv = path.clone();
+            mv.end_loc = square_to_cord(sq);
+            self.player_info.moves.push(mv);
+        }
+    }
 
-        // match last_player {
-        //     Player::Red => self.current_player = self.black_info.clone(),
-        //     Player::Black => self.current_player = self.red_info.clone(),
-        // };
+    fn calc_moves(&mut self) {
+        self.player_info.moves.clear();
+        let jumpers = self.jumpers();
+        self.player_info.can_jump = jumpers != 0;
+
+        if self.player_info.can_jump {
+            for sq in Squares(jumpers) {
+                let mut path = Moves::new_step(square_to_cord(sq), (9, 9));
+                self.dfs_jumps(sq, sq, 0, &mut path);
+            }
+            return;
+        }
 
-        self.calc_moves();
-        true
+        let empty = self.empty();
+        for dir in ALL_DIRECTIONS.iter() {
+            let back = dir.opposite();
+            for sq in Squares(dir.shift(self.movers(dir)) & empty) {
+                self.player_info.moves.push(Moves::new_step(
+                    square_to_cord(back.shift(1 << sq).trailing_zeros() as usize),
+                    square_to_cord(sq),
+                ));
+            }
+        }
     }
 
-    fn is_off_screen(&self, row: i32, col: i32) -> bool {
-        row >= self.board.len() as i32 || row < 0 || col >= self.board[0].len() as i32 || col < 0
+    // number of moves each player may make without a capture or a man moving before it is a draw
+    pub fn set_draw_move_limit(&mut self, moves: u32) {
+        self.draw_move_limit = moves;
     }
 
-    fn is_move_legal(&self, row: usize, col: usize, mv: Move) -> bool {
-        let player_info = self.players.get_current_player();
-        let piece = self.board[row][col];
-        // check its the correct player's turn for the selected piece commented out because I am
-        // only doing this off of the list of current players pieces
-        // if !((piece.is_red() && self.player_turn == Player::Red ) || (piece.is_black() && self.player_turn == Player::Black)){
-        //     return false;
-        // }
+    pub fn get_draw_move_limit(&self) -> u32 {
+        self.draw_move_limit
+    }
 
-        // let player_info = self.current_player.borrow();
-        if player_info.can_jump && mv != Move::Jump {
-            return false;
+    pub fn is_game_over(&self) -> Option<GameOutcome> {
+        if self.player_info.moves.is_empty() {
+            return Option::Some(GameOutcome::Win(self.player_info.player.get_other()));
+        }
+        if self.reversible_plies >= 2 * self.draw_move_limit {
+            return Option::Some(GameOutcome::Draw(DrawReason::NoProgress));
         }
+        if self.repetitions() >= 2 {
+            return Option::Some(GameOutcome::Draw(DrawReason::Repetition));
+        }
+        Option::None
+    }
 
-        if !piece.is_king() && mv.must_be_king() {
+    // how many times the current position came up before
+    fn repetitions(&self) -> usize {
+        let window = std::cmp::min(self.reversible_plies as usize, self.history.len());
+        self.history[self.history.len() - window..]
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .filter(|&&h| h == self.hash)
+            .count()
+    }
+
+    pub fn do_move(&mut self, mv: usize) -> bool {
+        if mv >= self.player_info.moves.len() {
             return false;
         }
+        self.make_move(mv);
+        true
+    }
 
-        let can_move_to = |new_row, new_col| {
-            if self.is_off_screen(new_row, new_col) {
-                false
-            } else {
-                self.board[new_row as usize][new_col as usize] == BoardPiece::Empty
-            }
+    // Plays the move at index `mv` of the current player's moves and returns what is needed to
+    // take it back with unmake_move. Panics if the index is out of range.
+    pub fn make_move(&mut self, mv: usize) -> UndoInfo {
+        let undo = UndoInfo {
+            black: self.black,
+            red: self.red,
+            kings: self.kings,
+            hash: self.hash,
+            reversible_plies: self.reversible_plies,
+            moves: std::mem::replace(
+                &mut self.player_info.moves,
+                self.spare_moves.pop().unwrap_or_default(),
+            ),
+            can_jump: self.player_info.can_jump,
         };
+        let move_obj = &undo.moves[mv];
 
-        let can_jump =
-            |enemy_row: i32, enemy_col: i32, new_row: i32, new_col: i32, player: Player| -> bool {
-                !(self.is_off_screen(enemy_row, enemy_col)
-                    || self.is_off_screen(new_row, new_col)
-                    || self.board[enemy_row as usize][enemy_col as usize] == BoardPiece::Empty
-                    || player.does_piece_match(self.board[enemy_row as usize][enemy_col as usize])
-                    || self.board[new_row as usize][new_col as usize] != BoardPiece::Empty)
-            };
+        let player = self.player_info.player;
+        let start = cord_to_bit(move_obj.start_loc);
+        let end = cord_to_bit(move_obj.end_loc);
+        let captured = move_obj.captured();
+        let start_sq = start.trailing_zeros() as usize;
+        let end_sq = end.trailing_zeros() as usize;
 
-        match mv {
-            Move::ForwardRight => {
-                let new_row = row as i32 + player_info.player as i32;
-                let new_col = col as i32 + 1;
-                can_move_to(new_row, new_col)
-            }
-            Move::ForwardLeft => {
-                let new_row = row as i32 + player_info.player as i32;
-                let new_col = col as i32 - 1;
-                can_move_to(new_row, new_col)
-            }
-            Move::BackwardRight => {
-                let new_row = row as i32 - player_info.player as i32;
-                let new_col = col as i32 + 1;
-                can_move_to(new_row, new_col)
-            }
-            Move::BackwardLeft => {
-                let new_row = row as i32 - player_info.player as i32;
-                let new_col = col as i32 - 1;
-                can_move_to(new_row, new_col)
-            }
-            Move::Jump => {
-                let mut can_jump_acc = false;
-                if piece.is_king() {
-                    can_jump_acc |= can_jump(
-                        row as i32 - player_info.player as i32,
-                        col as i32 + 1,
-                        row as i32 - 2 * (player_info.player as i32),
-                        col as i32 + 2,
-                        player_info.player,
-                    );
-                    can_jump_acc |= can_jump(
-                        row as i32 - player_info.player as i32,
-                        col as i32 - 1,
-                        row as i32 - 2 * (player_info.player as i32),
-                        col as i32 - 2,
-                        player_info.player,
-                    );
-                }
-                can_jump_acc |= can_jump(
-                    row as i32 + player_info.player as i32,
-                    col as i32 + 1,
-                    row as i32 + 2 * (player_info.player as i32),
-                    col as i32 + 2,
-                    player_info.player,
-                );
-                can_jump_acc |= can_jump(
-                    row as i32 + player_info.player as i32,
-                    col as i32 - 1,
-                    row as i32 + 2 * (player_info.player as i32),
-                    col as i32 - 2,
-                    player_info.player,
-                );
-                can_jump_acc
-            }
+        if captured != 0 || self.kings & start == 0 {
+            self.reversible_plies = 0;
+        } else {
+            self.reversible_plies += 1;
         }
+        self.history.push(self.hash);
+
+        self.hash ^= self.piece_key(start_sq);
+        for sq in Squares(captured) {
+            self.hash ^= self.piece_key(sq);
+        }
+
+        // a king can finish a jump on the square it started from, so clear before setting
+        *self.pieces_of_mut(player) = (self.pieces_of(player) & !start) | end;
+        *self.pieces_of_mut(player.get_other()) &= !captured;
+        if self.kings & start != 0 {
+            self.kings = (self.kings & !start) | end;
+        } else if end & player.promotion_row() != 0 {
+            self.kings |= end;
+        }
+        self.kings &= !captured;
+
+        self.hash ^= self.piece_key(end_sq) ^ zobrist::RED_TO_MOVE;
+        self.player_info.player = player.get_other();
+        self.calc_moves();
+        undo
+    }
+
+    // Takes back the move that returned this UndoInfo, it must be the last move made
+    pub fn unmake_move(&mut self, undo: UndoInfo) {
+        self.black = undo.black;
+        self.red = undo.red;
+        self.kings = undo.kings;
+        self.hash = undo.hash;
+        self.reversible_plies = undo.reversible_plies;
+        self.history.pop();
+        self.player_info.player = self.player_info.player.get_other();
+        let moves = std::mem::replace(&mut self.player_info.moves, undo.moves);
+        self.spare_moves.push(moves);
+        self.player_info.can_jump = undo.can_jump;
     }
 
     pub fn get_current_player(&self) -> Player {
-        self.players.get_current_player().player
+        self.player_info.player
     }
 }
 
@@ -860,11 +910,212 @@ mod test {
         println!("{}", board1);
         println!("{}", board2);
 
-        for (row1, row2) in board1.board.iter().zip(board2.board.iter()) {
+        for (row1, row2) in board1.to_array().iter().zip(board2.to_array().iter()) {
             for (col1, col2) in row1.iter().zip(row2.iter()) {
                 assert_eq!(*col1, *col2)
             }
         }
         Ok(())
     }
+
+    #[test]
+    fn test_square_mapping() {
+        for sq in 0..32 {
+            let (row, col) = square_to_cord(sq);
+            assert_eq!((row + col) % 2, 0);
+            assert_eq!(cord_to_square((row, col)), sq);
+        }
+    }
+
+    #[test]
+    fn test_forced_jump() {
+        let mut board = [[BoardPiece::Empty; BOARD_SIZE]; BOARD_SIZE];
+        board[2][2] = BoardPiece::Black;
+        board[2][6] = BoardPiece::Black;
+        board[3][3] = BoardPiece::Red;
+        board[6][6] = BoardPiece::Red;
+        let mut b = Board::from_array(board, Player::Black);
+
+        assert!(b.get_player_info().get_can_jump());
+        assert_eq!(b.get_player_info().get_moves().len(), 1);
+        assert!(b.do_move(0));
+
+        let (red, black) = b.get_pieces();
+        assert_eq!(red.len(), 1);
+        assert_eq!(black.len(), 2);
+        assert_eq!(b.to_array()[4][4], BoardPiece::Black);
+        assert_eq!(b.to_array()[3][3], BoardPiece::Empty);
+    }
+
+    #[test]
+    fn test_king_jump_ends_on_start() {
+        // the king takes all four men around it and lands back on the square it jumped from
+        let mut board = [[BoardPiece::Empty; BOARD_SIZE]; BOARD_SIZE];
+        board[2][4] = BoardPiece::KingBlack;
+        for (row, col) in [(3, 3), (3, 5), (5, 3), (5, 5)] {
+            board[row][col] = BoardPiece::Red;
+        }
+        let mut b = Board::from_array(board, Player::Black);
+
+        assert_eq!(b.get_player_info().get_moves().len(), 2);
+        assert!(b.do_move(0));
+
+        let (red, black) = b.get_pieces();
+        assert!(red.is_empty());
+        assert_eq!(black.len(), 1);
+        assert_eq!(b.to_array()[2][4], BoardPiece::KingBlack);
+    }
+
+    #[test]
+    fn test_ordered_jump_path() {
+        // a king surrounded by a ring of four pieces can go around it either way
+        let mut board = [[BoardPiece::Empty; BOARD_SIZE]; BOARD_SIZE];
+        board[2][4] = BoardPiece::KingBlack;
+        board[3][3] = BoardPiece::Red;
+        board[3][5] = BoardPiece::Red;
+        board[5][3] = BoardPiece::Red;
+        board[5][5] = BoardPiece::Red;
+        let b = Board::from_array(board, Player::Black);
+
+        let moves = b.get_player_info().get_moves();
+        assert_eq!(moves.len(), 2);
+        assert_ne!(moves[0], moves[1]);
+        for mv in moves {
+            assert_eq!(mv.get_start_loc(), (2, 4));
+            assert_eq!(mv.get_end_loc(), (2, 4));
+            assert_eq!(mv.get_jump_path().len(), 4);
+            assert_eq!(mv.get_landings().last(), Some(&(2, 4)));
+        }
+        let clockwise = moves
+            .iter()
+            .find(|mv| mv.get_jump_path()[0] == (3, 5))
+            .expect("route starting to the right");
+        assert_eq!(
+            clockwise.get_landings(),
+            &[(4, 6), (6, 4), (4, 2), (2, 4)][..]
+        );
+        assert_eq!(
+            clockwise.get_jump_path(),
+            &[(3, 5), (5, 5), (5, 3), (3, 3)][..]
+        );
+    }
+
+    #[test]
+    fn test_parse_move() {
+        let b = Board::default();
+        let mv = b.parse_move("11-15").unwrap();
+        assert_eq!(b.get_player_info().get_moves()[mv].notation(), "11-15");
+        assert!(b.parse_move("11-19").is_err());
+        assert!(b.parse_move("11-").is_err());
+        assert!(b.parse_move("33-29").is_err());
+
+        let mut board = [[BoardPiece::Empty; BOARD_SIZE]; BOARD_SIZE];
+        board[2][4] = BoardPiece::KingBlack;
+        board[3][3] = BoardPiece::Red;
+        board[3][5] = BoardPiece::Red;
+        board[5][3] = BoardPiece::Red;
+        board[5][5] = BoardPiece::Red;
+        let b = Board::from_array(board, Player::Black);
+        // both routes around the ring start and end on square 10
+        let err = b.parse_move("10x10").unwrap_err().to_string();
+        assert!(err.contains("Ambiguous"), "{}", err);
+        for mv in b.get_player_info().get_moves() {
+            let idx = b.parse_move(&mv.notation()).unwrap();
+            assert_eq!(&b.get_player_info().get_moves()[idx], mv);
+        }
+    }
+
+    #[test]
+    fn test_incremental_hash() {
+        let mut b = Board::default();
+        for _ in 0..40 {
+            if b.is_game_over().is_some() {
+                break;
+            }
+            let mv = b.get_hash() as usize % b.get_player_info().get_moves().len();
+            b.do_move(mv);
+            assert_eq!(b.get_hash(), b.calc_hash());
+        }
+        let before = b.get_hash();
+        b.swap_current_player();
+        assert_ne!(b.get_hash(), before);
+        assert_eq!(b.get_hash(), b.calc_hash());
+    }
+
+    #[test]
+    fn test_make_unmake() {
+        let mut b = Board::default();
+        for i in 0..60 {
+            if b.is_game_over().is_some() {
+                break;
+            }
+            let notations = |b: &Board| -> Vec<String> {
+                b.get_player_info()
+                    .get_moves()
+                    .iter()
+                    .map(|mv| mv.notation())
+                    .collect()
+            };
+            let moves = notations(&b);
+            let before = format!("{}", b.display_file());
+            let hash = b.get_hash();
+            for mv in 0..moves.len() {
+                let undo = b.make_move(mv);
+                b.unmake_move(undo);
+                assert_eq!(format!("{}", b.display_file()), before);
+                assert_eq!(b.get_hash(), hash);
+                // the moves come back from the undo info, in the same order
+                assert_eq!(notations(&b), moves);
+            }
+            b.make_move((hash as usize + i) % moves.len());
+        }
+    }
+
+    fn play(b: &mut Board, start: Cord, end: Cord) {
+        let mv = b
+            .get_player_info()
+            .get_moves()
+            .iter()
+            .position(|m| m.start_loc == start && m.end_loc == end)
+            .expect("move is not legal");
+        b.make_move(mv);
+    }
+
+    fn kings_in_corners() -> Board {
+        let mut board = [[BoardPiece::Empty; BOARD_SIZE]; BOARD_SIZE];
+        board[0][0] = BoardPiece::KingBlack;
+        board[7][7] = BoardPiece::KingRed;
+        Board::from_array(board, Player::Black)
+    }
+
+    #[test]
+    fn test_repetition_draw() {
+        let mut b = kings_in_corners();
+        for _ in 0..2 {
+            assert_eq!(b.is_game_over(), None);
+            play(&mut b, (0, 0), (1, 1));
+            play(&mut b, (7, 7), (6, 6));
+            play(&mut b, (1, 1), (0, 0));
+            play(&mut b, (6, 6), (7, 7));
+        }
+        assert_eq!(
+            b.is_game_over(),
+            Some(GameOutcome::Draw(DrawReason::Repetition))
+        );
+    }
+
+    #[test]
+    fn test_no_progress_draw() {
+        let mut b = kings_in_corners();
+        b.set_draw_move_limit(2);
+        play(&mut b, (0, 0), (1, 1));
+        play(&mut b, (7, 7), (6, 6));
+        play(&mut b, (1, 1), (2, 2));
+        assert_eq!(b.is_game_over(), None);
+        play(&mut b, (6, 6), (5, 5));
+        assert_eq!(
+            b.is_game_over(),
+            Some(GameOutcome::Draw(DrawReason::NoProgress))
+        );
+    }
 }
@@ -0,0 +1,467 @@
+// Reading and writing games in Portable Draughts Notation. Black is the first player (the "Black"
+// tag and the first number of a result), red plays the part of white.
+use super::{Board, GameOutcome, Player, Squares};
+use anyhow::{anyhow, bail, Context, Result};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PdnResult {
+    BlackWin,
+    RedWin,
+    Draw,
+    #[default]
+    Unfinished,
+}
+
+impl PdnResult {
+    fn parse(token: &str) -> Option<Self> {
+        match token {
+            "1-0" | "2-0" => Some(Self::BlackWin),
+            "0-1" | "0-2" => Some(Self::RedWin),
+            "1/2-1/2" | "1-1" => Some(Self::Draw),
+            "*" => Some(Self::Unfinished),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BlackWin => "1-0",
+            Self::RedWin => "0-1",
+            Self::Draw => "1/2-1/2",
+            Self::Unfinished => "*",
+        }
+    }
+}
+
+impl From<Option<GameOutcome>> for PdnResult {
+    fn from(value: Option<GameOutcome>) -> Self {
+        match value {
+            Some(GameOutcome::Win(Player::Black)) => Self::BlackWin,
+            Some(GameOutcome::Win(Player::Red)) => Self::RedWin,
+            Some(GameOutcome::Draw(_)) => Self::Draw,
+            None => Self::Unfinished,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PdnGame {
+    tags: Vec<(String, String)>,
+    // move text exactly as it will be written, eg. "11-15" or "22x15x8"
+    moves: Vec<String>,
+    result: PdnResult,
+}
+
+impl PdnGame {
+    // an empty game starting from this position
+    pub fn new(start: &Board) -> Self {
+        let mut game = Self::default();
+        game.set_tag("Event", "?");
+        game.set_tag("Black", "?");
+        game.set_tag("White", "?");
+        game.set_tag("Result", PdnResult::Unfinished.as_str());
+        game.set_tag("GameType", "21");
+        if !start.is_start_position() {
+            game.set_tag("FEN", &start.to_fen());
+        }
+        game
+    }
+
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn get_moves(&self) -> &[String] {
+        &self.moves
+    }
+
+    pub fn get_result(&self) -> PdnResult {
+        self.result
+    }
+
+    pub fn set_result(&mut self, result: PdnResult) {
+        self.result = result;
+        self.set_tag("Result", result.as_str());
+    }
+
+    // records move `mv` of the board, call it before the move is played
+    pub fn push_move(&mut self, b: &Board, mv: usize) {
+        self.moves
+            .push(b.get_player_info().get_moves()[mv].notation());
+    }
+
+    pub fn start_board(&self) -> Result<Board> {
+        match self.get_tag("FEN") {
+            Some(fen) => Board::from_fen(fen),
+            None => Ok(Board::default()),
+        }
+    }
+
+    // every position of the game paired with the index of the move that was played from it
+    pub fn replay(&self) -> Result<Vec<(Board, usize)>> {
+        let mut b = self.start_board()?;
+        let mut positions = Vec::with_capacity(self.moves.len());
+        for (i, text) in self.moves.iter().enumerate() {
+            let mv = b
+                .parse_move(text)
+                .with_context(|| format!("in move {}", i / 2 + 1))?;
+            positions.push((b.clone(), mv));
+            b.make_move(mv);
+        }
+        Ok(positions)
+    }
+
+    // the position after the last move of the game
+    pub fn final_board(&self) -> Result<Board> {
+        let mut b = self.start_board()?;
+        for (i, text) in self.moves.iter().enumerate() {
+            let mv = b
+                .parse_move(text)
+                .with_context(|| format!("in move {}", i / 2 + 1))?;
+            b.make_move(mv);
+        }
+        Ok(b)
+    }
+}
+
+impl std::fmt::Display for PdnGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, value) in self.tags.iter() {
+            writeln!(
+                f,
+                "[{} \"{}\"]",
+                name,
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            )?;
+        }
+        writeln!(f)?;
+
+        // move numbers count black and red moves as one, a game red starts begins with "1..."
+        let red_first = match self.start_board() {
+            Ok(b) => b.get_current_player() == Player::Red,
+            Err(_) => false,
+        };
+        let mut line = String::new();
+        for (i, text) in self.moves.iter().enumerate() {
+            let ply = i + red_first as usize;
+            let token = match (ply.is_multiple_of(2), i == 0) {
+                (true, _) => format!("{}. {}", ply / 2 + 1, text),
+                (false, true) => format!("{}... {}", ply / 2 + 1, text),
+                (false, false) => text.clone(),
+            };
+            if !line.is_empty() && line.len() + token.len() >= 80 {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        writeln!(f, "{}{}", line, self.result.as_str())
+    }
+}
+
+impl FromStr for PdnGame {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_pdn(s)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No game found in PDN"))
+    }
+}
+
+// parses every game in a PDN file, comments and variations are skipped
+pub fn parse_pdn(s: &str) -> Result<Vec<PdnGame>> {
+    let mut games = vec![];
+    let mut game = PdnGame::default();
+    let mut in_movetext = false;
+    let mut chars = s.chars().peekable();
+
+    let finish = |games: &mut Vec<PdnGame>, game: &mut PdnGame| {
+        games.push(std::mem::take(game));
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                // a tag after the moves means the previous game had no result
+                if in_movetext {
+                    finish(&mut games, &mut game);
+                    in_movetext = false;
+                }
+                let mut name = String::new();
+                let mut value = String::new();
+                let mut in_quotes = false;
+                loop {
+                    match chars.next() {
+                        None => bail!("Unterminated PDN tag [{}", name),
+                        Some('\\') if in_quotes => {
+                            if let Some(escaped) = chars.next() {
+                                value.push(escaped);
+                            }
+                        }
+                        Some('"') => in_quotes = !in_quotes,
+                        Some(']') if !in_quotes => break,
+                        Some(ch) if in_quotes => value.push(ch),
+                        Some(ch) => name.push(ch),
+                    }
+                }
+                if name.trim().is_empty() {
+                    bail!("Invalid PDN tag with value {:?}", value);
+                }
+                game.tags.push((name.trim().to_string(), value));
+            }
+            '{' => {
+                for ch in chars.by_ref() {
+                    if ch == '}' {
+                        break;
+                    }
+                }
+            }
+            ';' => {
+                for ch in chars.by_ref() {
+                    if ch == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => {
+                let mut depth = 1;
+                for ch in chars.by_ref() {
+                    match ch {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = String::from(c);
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || "[{(;".contains(ch) {
+                        break;
+                    }
+                    token.push(ch);
+                    chars.next();
+                }
+                if let Some(result) = PdnResult::parse(&token) {
+                    game.result = result;
+                    finish(&mut games, &mut game);
+                    in_movetext = false;
+                    continue;
+                }
+                in_movetext = true;
+                // move numbers are either their own token ("12." or "12...") or glued to the move
+                let mv = match token.find(|ch: char| !ch.is_ascii_digit()) {
+                    Some(i) if i > 0 && token[i..].starts_with('.') => {
+                        token[i..].trim_start_matches('.')
+                    }
+                    _ => token.as_str(),
+                };
+                let mv = mv.trim_end_matches(['!', '?', '*']);
+                if mv.is_empty() || mv.starts_with('$') {
+                    continue;
+                }
+                game.moves.push(mv.to_string());
+            }
+        }
+    }
+    if in_movetext || !game.tags.is_empty() {
+        finish(&mut games, &mut game);
+    }
+    Ok(games)
+}
+
+impl Board {
+    fn is_start_position(&self) -> bool {
+        let start = Board::default();
+        self.black == start.black
+            && self.red == start.red
+            && self.kings == start.kings
+            && self.get_current_player() == start.get_current_player()
+    }
+
+    // the position as a PDN FEN string, eg. "B:W21,22,K30:B1,K2"
+    pub fn to_fen(&self) -> String {
+        let pieces = |bb: u32| {
+            Squares(bb)
+                .map(|sq| match self.kings & (1 << sq) != 0 {
+                    true => format!("K{}", sq + 1),
+                    false => format!("{}", sq + 1),
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let turn = match self.get_current_player() {
+            Player::Black => 'B',
+            Player::Red => 'W',
+        };
+        format!("{}:W{}:B{}", turn, pieces(self.red), pieces(self.black))
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Self> {
+        let fen = fen.trim().trim_end_matches('.');
+        let mut sections = fen.split(':');
+        let player = match sections.next().map(str::trim) {
+            Some("B") => Player::Black,
+            Some("W") => Player::Red,
+            _ => bail!(
+                "Invalid FEN {:?}: it must start with the player to move (B or W)",
+                fen
+            ),
+        };
+
+        let (mut black, mut red, mut kings) = (0u32, 0u32, 0u32);
+        // every square listed so far, by either side
+        let mut taken = 0u32;
+        for section in sections {
+            let section = section.trim();
+            let side = match section.chars().next() {
+                Some('B') => &mut black,
+                Some('W') => &mut red,
+                _ => bail!("Invalid FEN {:?}: unknown side in {:?}", fen, section),
+            };
+            for piece in section[1..].split(',').map(str::trim) {
+                if piece.is_empty() {
+                    continue;
+                }
+                let (is_king, squares) = match piece.strip_prefix('K') {
+                    Some(rest) => (true, rest),
+                    None => (false, piece),
+                };
+                let (first, last) = squares.split_once('-').unwrap_or((squares, squares));
+                let parse = |n: &str| match n.parse::<u32>() {
+                    Ok(n @ 1..=32) => Ok(n),
+                    _ => Err(anyhow!("Invalid FEN {:?}: bad square {:?}", fen, n)),
+                };
+                let (first, last) = (parse(first)?, parse(last)?);
+                if first > last {
+                    bail!(
+                        "Invalid FEN {:?}: the range {:?} runs backwards",
+                        fen,
+                        squares
+                    );
+                }
+                for n in first..=last {
+                    let bit = 1 << (n - 1);
+                    if taken & bit != 0 {
+                        bail!("Invalid FEN {:?}: square {} has two pieces", fen, n);
+                    }
+                    taken |= bit;
+                    *side |= bit;
+                    if is_king {
+                        kings |= bit;
+                    }
+                }
+            }
+        }
+        Board::from_bitboards(black, red, kings, player)
+            .with_context(|| format!("Invalid FEN {:?}", fen))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fen_round_trip() -> Result<()> {
+        let start = Board::default();
+        assert_eq!(
+            start.to_fen(),
+            "B:W21,22,23,24,25,26,27,28,29,30,31,32:B1,2,3,4,5,6,7,8,9,10,11,12"
+        );
+        let b = Board::from_fen("W:WK3,21-22:B1,K30.")?;
+        assert_eq!(b.to_fen(), "W:WK3,21,22:B1,K30");
+        assert_eq!(b.get_current_player(), Player::Red);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_and_write() -> Result<()> {
+        let text = r#"[Event "Test \"game\""]
+[Black "a"]
+[White "b"]
+[Result "1-0"]
+
+1. 11-15 22-18 {a comment} 2. 15x22 (2. 8-11) 25x18 3. 8-11 1-0
+
+[Event "Second"]
+1. 9-14 *
+"#;
+        let games = parse_pdn(text)?;
+        assert_eq!(games.len(), 2);
+        let game = &games[0];
+        assert_eq!(game.get_tag("Event"), Some("Test \"game\""));
+        assert_eq!(game.get_result(), PdnResult::BlackWin);
+        assert_eq!(game.get_moves().len(), 5);
+        assert_eq!(game.replay()?.len(), 5);
+
+        let written: PdnGame = format!("{}", game).parse()?;
+        assert_eq!(written.get_moves(), game.get_moves());
+        assert_eq!(written.get_tag("Event"), game.get_tag("Event"));
+        assert_eq!(
+            written.final_board()?.to_fen(),
+            game.final_board()?.to_fen()
+        );
+        assert_eq!(games[1].get_result(), PdnResult::Unfinished);
+        Ok(())
+    }
+
+    #[test]
+    fn test_glued_move_numbers() -> Result<()> {
+        let games = parse_pdn("1.11-15 22-18 2.15x22 25x18 3...8-11 *")?;
+        assert_eq!(
+            games[0].get_moves(),
+            ["11-15", "22-18", "15x22", "25x18", "8-11"]
+        );
+        assert_eq!(
+            games[0].final_board()?.to_fen(),
+            "W:W18,21,23,24,26,27,28,29,30,31,32:B1,2,3,4,5,6,7,9,10,11,12"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_fen() {
+        // a man on the row it is crowned on
+        assert!(Board::from_fen("B:W21:B29").is_err());
+        assert!(Board::from_fen("B:W21:BK29").is_ok());
+        // two pieces on a square
+        assert!(Board::from_fen("B:W10:B10").is_err());
+        assert!(Board::from_fen("B:W21,21:B1").is_err());
+        assert!(Board::from_fen("B:W21-24,22:B1").is_err());
+        // ranges that run backwards or miss an end
+        assert!(Board::from_fen("B:W21-19:B1").is_err());
+        assert!(Board::from_fen("B:W21-:B1").is_err());
+        // more men than a side starts with
+        assert!(Board::from_fen("B:W17-28,29:B1").is_err());
+    }
+
+    #[test]
+    fn test_illegal_move() {
+        let game: PdnGame = "1. 11-20 *".parse().unwrap();
+        assert!(game.final_board().is_err());
+    }
+}
@@ -0,0 +1,156 @@
+// Counts the leaf nodes of the full move tree, used to check move generation against known
+// numbers. Draw rules are ignored, a position only ends when the player to move has no moves.
+
+use super::Board;
+
+impl Board {
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        let moves = self.player_info.moves.len();
+        match depth {
+            0 => 1,
+            // the moves of the last ply don't have to be made to be counted
+            1 => moves as u64,
+            _ => (0..moves)
+                .map(|mv| {
+                    let undo = self.make_move(mv);
+                    let nodes = self.perft(depth - 1);
+                    self.unmake_move(undo);
+                    nodes
+                })
+                .sum(),
+        }
+    }
+
+    // the perft count below every root move, written in notation
+    pub fn perft_divide(&mut self, depth: u32) -> Vec<(String, u64)> {
+        (0..self.player_info.moves.len())
+            .map(|mv| {
+                let notation = self.player_info.moves[mv].notation();
+                let undo = self.make_move(mv);
+                let nodes = self.perft(depth.saturating_sub(1));
+                self.unmake_move(undo);
+                (notation, nodes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // published counts for the starting position
+    const START_PERFT: [u64; 10] = [1, 7, 49, 302, 1469, 7361, 36768, 179740, 845931, 3963680];
+
+    #[test]
+    fn test_start_position() {
+        let mut b = Board::default();
+        for (depth, &nodes) in START_PERFT.iter().enumerate() {
+            assert_eq!(b.perft(depth as u32), nodes, "depth {}", depth);
+        }
+        // make/unmake has to leave the board as it was
+        assert_eq!(b.get_hash(), Board::default().get_hash());
+        assert_eq!(b.to_fen(), Board::default().to_fen());
+    }
+
+    #[test]
+    fn test_divide_sums_to_perft() {
+        let mut b = Board::default();
+        let divide = b.perft_divide(5);
+        assert_eq!(divide.len(), 7);
+        assert_eq!(divide.iter().map(|(_, n)| n).sum::<u64>(), START_PERFT[5]);
+    }
+
+    // counts the tree again by cloning the board for every move instead of make/unmake
+    fn perft_by_clone(b: &Board, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        (0..b.get_player_info().get_moves().len())
+            .map(|mv| {
+                let mut next = b.clone();
+                next.do_move(mv);
+                perft_by_clone(&next, depth - 1)
+            })
+            .sum()
+    }
+
+    fn perft_of(fen: &str, depth: u32) -> u64 {
+        let mut b = Board::from_fen(fen).unwrap();
+        let nodes = b.perft(depth);
+        assert_eq!(nodes, perft_by_clone(&b, depth), "{} depth {}", fen, depth);
+        nodes
+    }
+
+    fn check_counts(fen: &str, counts: &[u64]) {
+        for (depth, &nodes) in counts.iter().enumerate() {
+            assert_eq!(
+                perft_of(fen, depth as u32),
+                nodes,
+                "{} depth {}",
+                fen,
+                depth
+            );
+        }
+    }
+
+    #[test]
+    fn test_multi_jump() {
+        // the king on 10 can go around the ring of four men either way, after that red is out of
+        // pieces and the game is over
+        let fen = "B:W14,15,22,23:BK10";
+        assert_eq!(perft_of(fen, 1), 2);
+        assert_eq!(perft_of(fen, 2), 0);
+
+        // the man on 10 can take 14 and 22 or 15 and 24, stopping after one isn't allowed
+        let fen = "B:W14,15,22,24:B10";
+        assert_eq!(perft_of(fen, 1), 2);
+        let b = Board::from_fen(fen).unwrap();
+        let mut moves: Vec<String> = b
+            .get_player_info()
+            .get_moves()
+            .iter()
+            .map(|mv| mv.notation())
+            .collect();
+        moves.sort();
+        assert_eq!(moves, ["10x17x26", "10x19x28"]);
+    }
+
+    #[test]
+    fn test_promotion() {
+        // a man that is crowned by a jump stops there even if it could jump on as a king
+        let fen = "B:W25,26:B23";
+        assert_eq!(perft_of(fen, 1), 1);
+        let mut b = Board::from_fen(fen).unwrap();
+        assert_eq!(b.get_player_info().get_moves()[0].notation(), "23x30");
+        b.do_move(0);
+        assert_eq!(b.to_fen(), "W:W25:BK30");
+        assert_eq!(perft_of(fen, 2), 2);
+
+        // a king moves in every direction, a man only forwards
+        assert_eq!(perft_of("B:W32:BK18", 1), 4);
+        assert_eq!(perft_of("B:W32:B18", 1), 2);
+    }
+
+    #[test]
+    fn test_tricky_positions() {
+        // no published tables exist for these, the counts were checked against the old
+        // array based move generator, which shares no code with the bitboards
+
+        // black's first move is always a double jump, 10x17x26 or 10x19x26
+        check_counts(
+            "B:W14,15,22,23,30:B1,2,3,10",
+            &[1, 2, 6, 42, 159, 775, 3162, 14798, 56314],
+        );
+
+        // only kings, which move backwards and can jump in every direction
+        check_counts(
+            "W:WK1,K3,K28,30:BK5,K20,K29,K32",
+            &[1, 6, 23, 122, 723, 4213, 24010, 140548],
+        );
+        check_counts(
+            "B:WK9,K14,K22,K23,K27:BK1,K18,K32",
+            &[1, 1, 11, 54, 520, 2361, 18941],
+        );
+    }
+}
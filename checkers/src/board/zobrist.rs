@@ -0,0 +1,35 @@
+// Random keys used to build an incremental hash of a position. They are generated at compile
+// time with splitmix64 so every build (and every saved hash) agrees on them.
+
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+const fn generate_keys() -> ([[u64; 32]; 4], u64) {
+    let mut keys = [[0; 32]; 4];
+    let mut state = 0x5EED_C4EC_4E25_u64;
+    let mut piece = 0;
+    while piece < 4 {
+        let mut sq = 0;
+        while sq < 32 {
+            let (next, key) = splitmix64(state);
+            state = next;
+            keys[piece][sq] = key;
+            sq += 1;
+        }
+        piece += 1;
+    }
+    let (_, side) = splitmix64(state);
+    (keys, side)
+}
+
+const KEYS: ([[u64; 32]; 4], u64) = generate_keys();
+
+// indexed by `BoardPiece as usize` and then by square
+pub(super) const PIECE_KEYS: [[u64; 32]; 4] = KEYS.0;
+// xored in when red is the player to move
+pub(super) const RED_TO_MOVE: u64 = KEYS.1;